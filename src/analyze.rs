@@ -0,0 +1,619 @@
+use crate::code::Builtin;
+use crate::error::GsError;
+use crate::format::parse;
+use crate::parse::{Gtoken, Span, Token};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// How a piece of code changes the stack: it takes `takes` values from
+/// under where it starts and leaves `leaves` in their place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Effect {
+    pub takes: usize,
+    pub leaves: usize,
+}
+
+impl Effect {
+    /// How many more values there are afterwards.
+    pub fn net(self) -> isize {
+        self.leaves as isize - self.takes as isize
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({} -- {})", self.takes, self.leaves)
+    }
+}
+
+/// Something in a program that looks like a stack mistake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub span: Span,
+    pub message: String,
+}
+
+/// What [`analyze`] found out.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    /// The effect of the whole program, if it could be worked out.
+    pub program: Option<Effect>,
+    /// Every block literal, in source order, with its effect when run, if
+    /// it could be worked out.
+    pub blocks: Vec<(Span, Option<Effect>)>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Works out the stack effects of `source` and its blocks without running
+/// it, as far as they follow from the tokens alone, and warns about `if`
+/// branches that leave different numbers of values, loop bodies that don't
+/// leave a condition, and the like.
+///
+/// Values are only told apart by what their literals show, so an operator
+/// whose effect depends on the type of a value that came from elsewhere,
+/// such as `(` or `~`, leaves the rest of its block undetermined.
+///
+/// ```
+/// use golfscript_rs::{analyze, Effect};
+///
+/// let analysis = analyze(b"~{.}{;}if")?;
+/// assert_eq!(analysis.program, None);
+/// assert_eq!(
+///     analysis.warnings[0].message,
+///     "`if` branches leave different numbers of values: {.} (1 -- 2), {;} (1 -- 0)"
+/// );
+/// let analysis = analyze(b"{2+}%")?;
+/// assert_eq!(analysis.program, Some(Effect { takes: 1, leaves: 1 }));
+/// # Ok::<(), golfscript_rs::GsError>(())
+/// ```
+pub fn analyze(source: &[u8]) -> Result<Analysis, GsError> {
+    let tokens = parse(source)?;
+    let mut analyzer = Analyzer {
+        source,
+        assigned: HashSet::new(),
+        blocks: vec![],
+        warnings: vec![],
+    };
+    assignments(&tokens, &mut analyzer.assigned);
+    let program = analyzer.run(&tokens, State::default());
+    Ok(Analysis {
+        program: program.map(|s| s.effect()),
+        blocks: analyzer.blocks,
+        warnings: analyzer.warnings,
+    })
+}
+
+/// What is known of a value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ty {
+    Int,
+    /// A string or array.
+    List,
+    /// A block, with its effect if known, and its span if it's a literal.
+    Block(Option<Effect>, Option<Span>),
+    Any,
+}
+
+#[derive(Clone, Default)]
+struct State {
+    /// How many values have been taken from under the start.
+    below: usize,
+    /// The values above that.
+    stack: Vec<Ty>,
+    /// Where each open `[` is, as a height above the start.
+    marks: Vec<isize>,
+    vars: HashMap<Vec<u8>, Ty>,
+}
+
+impl State {
+    fn effect(&self) -> Effect {
+        Effect {
+            takes: self.below,
+            leaves: self.stack.len(),
+        }
+    }
+
+    fn height(&self) -> isize {
+        self.stack.len() as isize - self.below as isize
+    }
+
+    fn pop(&mut self) -> Ty {
+        let ty = self.stack.pop().unwrap_or_else(|| {
+            self.below += 1;
+            Ty::Any
+        });
+        // popping under a `[` moves it down, as `]` would see it
+        let height = self.height();
+        for mark in &mut self.marks {
+            *mark = (*mark).min(height);
+        }
+        ty
+    }
+
+    fn peek(&mut self, n: usize) -> Ty {
+        while self.stack.len() <= n {
+            self.below += 1;
+            self.stack.insert(0, Ty::Any);
+        }
+        self.stack[self.stack.len() - 1 - n]
+    }
+
+    fn push(&mut self, ty: Ty) {
+        self.stack.push(ty);
+    }
+
+    /// Runs code with effect `e`.
+    fn apply(&mut self, e: Effect) {
+        for _ in 0..e.takes {
+            self.pop();
+        }
+        for _ in 0..e.leaves {
+            self.push(Ty::Any);
+        }
+    }
+}
+
+struct Analyzer<'a> {
+    source: &'a [u8],
+    /// The names assigned anywhere, which may hold code when they run.
+    assigned: HashSet<Vec<u8>>,
+    blocks: Vec<(Span, Option<Effect>)>,
+    warnings: Vec<Warning>,
+}
+
+fn assignments(tokens: &[Token], assigned: &mut HashSet<Vec<u8>>) {
+    let mut tokens = tokens.iter();
+    while let Some(token) = tokens.next() {
+        match &token.gtoken {
+            Gtoken::Symbol(b":") => {
+                if let Some(name) = tokens.next() {
+                    assigned.insert(name.gtoken.lexeme().to_vec());
+                }
+            }
+            Gtoken::Block(inner, _) => assignments(inner, assigned),
+            _ => {}
+        }
+    }
+}
+
+impl Analyzer<'_> {
+    fn warn(&mut self, span: Span, message: String) {
+        self.warnings.push(Warning { span, message });
+    }
+
+    /// Runs `tokens` from `state`. `None` once the stack can't be told.
+    fn run(&mut self, tokens: &[Token], mut state: State) -> Option<State> {
+        let mut known = true;
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            let stepped = match &token.gtoken {
+                Gtoken::Comment(_) => true,
+                Gtoken::IntLiteral(_) => {
+                    state.push(Ty::Int);
+                    true
+                }
+                Gtoken::SingleQuotedString(_) | Gtoken::DoubleQuotedString(_) => {
+                    state.push(Ty::List);
+                    true
+                }
+                Gtoken::Block(inner, _) => {
+                    let effect = self.block(token.span, inner, &state);
+                    state.push(Ty::Block(effect, Some(token.span)));
+                    true
+                }
+                Gtoken::Symbol(b":") => {
+                    let ty = state.peek(0);
+                    if let Some(name) = tokens.next() {
+                        state.vars.insert(name.gtoken.lexeme().to_vec(), ty);
+                    }
+                    true
+                }
+                Gtoken::Symbol(name) => match state.vars.get(*name) {
+                    Some(Ty::Block(Some(e), _)) => {
+                        state.apply(*e);
+                        true
+                    }
+                    Some(Ty::Block(None, _)) => false,
+                    Some(&ty) => {
+                        state.push(ty);
+                        true
+                    }
+                    // a name assigned elsewhere may hold anything by now
+                    None if self.assigned.contains(*name) => false,
+                    None => match Builtin::from_name(name) {
+                        Some(builtin) => self.builtin(builtin, token.span, &mut state),
+                        // whitespace, or a name that was never given a value
+                        None => true,
+                    },
+                },
+            };
+            if !stepped {
+                // start over from here, for the warnings further on
+                known = false;
+                state = State {
+                    vars: std::mem::take(&mut state.vars),
+                    ..State::default()
+                };
+            }
+        }
+        known.then_some(state)
+    }
+
+    /// Works out and records the effect of the block literal `inner`.
+    fn block(&mut self, span: Span, inner: &[Token], state: &State) -> Option<Effect> {
+        let index = self.blocks.len();
+        self.blocks.push((span, None));
+        let start = State {
+            vars: state.vars.clone(),
+            ..State::default()
+        };
+        let effect = self.run(inner, start).map(|s| s.effect());
+        self.blocks[index].1 = effect;
+        effect
+    }
+
+    fn text(&self, span: Span) -> String {
+        String::from_utf8_lossy(&self.source[span.start..span.end]).into_owned()
+    }
+
+    /// Runs `builtin`, returning whether the stack is still known.
+    fn builtin(&mut self, builtin: Builtin, span: Span, state: &mut State) -> bool {
+        use Builtin::*;
+        let simple = |takes, leaves| Effect { takes, leaves };
+        let effect = match builtin {
+            Tilde => match state.pop() {
+                Ty::Block(Some(e), _) => e,
+                Ty::Int => simple(0, 1),
+                _ => return false,
+            },
+            Backtick | Bang | Rand | Abs | Zip | Read => simple(1, 1),
+            AtSign => {
+                let (c, b, a) = (state.pop(), state.pop(), state.pop());
+                state.push(b);
+                state.push(c);
+                state.push(a);
+                return true;
+            }
+            Backslash => {
+                let (b, a) = (state.pop(), state.pop());
+                state.push(b);
+                state.push(a);
+                return true;
+            }
+            Dot => {
+                let a = state.peek(0);
+                state.push(a);
+                return true;
+            }
+            Semicolon | Print | P | Puts => simple(1, 0),
+            N | Gets => simple(0, 1),
+            Minus | VerticalBar | Ampersand | Caret | Less | Equal | Greater | Base | Xor => {
+                simple(2, 1)
+            }
+            Plus => {
+                let (b, a) = (state.pop(), state.pop());
+                let joined = match (a, b) {
+                    (Ty::Int, Ty::Int) => Ty::Int,
+                    (Ty::Block(..), _) | (_, Ty::Block(..)) => Ty::Block(None, None),
+                    _ => Ty::Any,
+                };
+                state.push(joined);
+                return true;
+            }
+            LeftBracket => {
+                state.marks.push(state.height());
+                return true;
+            }
+            RightBracket => {
+                let Some(mark) = state.marks.pop() else {
+                    return false;
+                };
+                for _ in mark..state.height() {
+                    state.pop();
+                }
+                state.push(Ty::List);
+                return true;
+            }
+            LeftParen | RightParen => match state.pop() {
+                Ty::Int => simple(0, 1),
+                Ty::List | Ty::Block(..) => simple(0, 2),
+                Ty::Any => return false,
+            },
+            Comma => match state.pop() {
+                Ty::Block(e, _) => {
+                    self.expect(span, "`,` block", e, simple(1, 1));
+                    simple(1, 1)
+                }
+                _ => simple(0, 1),
+            },
+            Question => match state.pop() {
+                Ty::Block(e, _) => {
+                    self.expect(span, "`?` block", e, simple(1, 1));
+                    simple(1, 1)
+                }
+                _ => simple(1, 1),
+            },
+            Dollar => match state.pop() {
+                Ty::Block(e, _) => {
+                    self.expect(span, "`$` block", e, simple(1, 1));
+                    simple(1, 1)
+                }
+                _ => simple(0, 1),
+            },
+            Asterisk | Slash | Percent => {
+                let (b, a) = (state.pop(), state.pop());
+                let (block, other) = match (a, b) {
+                    (Ty::Block(e, _), other) | (other, Ty::Block(e, _)) => (e, other),
+                    // either may be a block, which runs
+                    (Ty::Any, _) | (_, Ty::Any) => return false,
+                    _ => {
+                        state.push(Ty::Any);
+                        return true;
+                    }
+                };
+                return self.loop_block(builtin, span, block, other, state);
+            }
+            And | Or => {
+                let (b, a) = (state.pop(), state.pop());
+                // whichever is left runs if it's a block, and `a` is left
+                // when it decides
+                if matches!(a, Ty::Any | Ty::Block(..)) || b == Ty::Any {
+                    return false;
+                }
+                if let Ty::Block(e, _) = b {
+                    let what = if builtin == And { "`and`" } else { "`or`" };
+                    if !self.expect(span, &format!("{} block", what), e, simple(0, 1)) {
+                        return false;
+                    }
+                }
+                simple(0, 1)
+            }
+            If => {
+                let (otherwise, then, _) = (state.pop(), state.pop(), state.pop());
+                // a value that may be a block may run
+                let branch = |ty| match ty {
+                    Ty::Block(e, _) => e,
+                    Ty::Any => None,
+                    Ty::Int | Ty::List => Some(simple(0, 1)),
+                };
+                match (branch(then), branch(otherwise)) {
+                    (Some(a), Some(b)) if a.net() == b.net() => {
+                        let takes = a.takes.max(b.takes);
+                        simple(takes, (takes as isize + a.net()) as usize)
+                    }
+                    (Some(a), Some(b)) => {
+                        let message = format!(
+                            "`if` branches leave different numbers of values: {}, {}",
+                            self.described(then, a),
+                            self.described(otherwise, b)
+                        );
+                        self.warn(span, message);
+                        return false;
+                    }
+                    _ => return false,
+                }
+            }
+            Do => {
+                let Ty::Block(Some(body), _) = state.pop() else {
+                    return false;
+                };
+                if body.net() != 1 {
+                    self.warn(
+                        span,
+                        format!(
+                            "`do` body should leave one more value than it takes, for the condition, but is {}",
+                            body
+                        ),
+                    );
+                    return false;
+                }
+                state.apply(body);
+                simple(1, 0)
+            }
+            While | Until => {
+                let (body, cond) = (state.pop(), state.pop());
+                let (Ty::Block(Some(body), _), Ty::Block(Some(cond), _)) = (body, cond) else {
+                    return false;
+                };
+                let name = if builtin == While { "while" } else { "until" };
+                if cond.net() != 1 {
+                    self.warn(
+                        span,
+                        format!(
+                            "`{}` condition should leave one more value than it takes, but is {}",
+                            name, cond
+                        ),
+                    );
+                    return false;
+                }
+                if body.net() != 0 {
+                    self.warn(
+                        span,
+                        format!(
+                            "`{}` body should leave as many values as it takes, but is {}",
+                            name, body
+                        ),
+                    );
+                    return false;
+                }
+                state.apply(cond);
+                state.pop();
+                body
+            }
+        };
+        state.apply(effect);
+        true
+    }
+
+    /// `*`, `/` or `%` with a block: repeat, fold, each or map.
+    fn loop_block(
+        &mut self,
+        builtin: Builtin,
+        span: Span,
+        block: Option<Effect>,
+        other: Ty,
+        state: &mut State,
+    ) -> bool {
+        let simple = |takes, leaves| Effect { takes, leaves };
+        match (builtin, other) {
+            (Builtin::Asterisk, Ty::Int) => match block {
+                // repeating code that keeps the stack as deep is like
+                // running it once
+                Some(e) if e.net() == 0 => state.apply(e),
+                _ => return false,
+            },
+            (Builtin::Asterisk, Ty::List) => {
+                if !self.expect(span, "fold block", block, simple(2, 1)) {
+                    return false;
+                }
+                state.push(Ty::Any);
+            }
+            (Builtin::Slash, Ty::List) => match block {
+                // each element is pushed and taken
+                Some(e) if e.net() == -1 => state.apply(simple(e.takes - 1, e.leaves)),
+                _ => return false,
+            },
+            (Builtin::Percent, Ty::List | Ty::Any) => {
+                match block {
+                    Some(e) if e.takes > 1 => self.warn(
+                        span,
+                        format!(
+                            "map block takes {} values, but only its element is there to take",
+                            e.takes
+                        ),
+                    ),
+                    Some(_) => {}
+                    None => self.warn(
+                        span,
+                        "map block's effect depends on the values it's given, so how many it takes can't be checked".to_owned(),
+                    ),
+                }
+                state.push(Ty::List);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Warns unless a block that `what` runs has the effect `want`. Returns
+    /// whether it does.
+    fn expect(&mut self, span: Span, what: &str, got: Option<Effect>, want: Effect) -> bool {
+        match got {
+            Some(got) if got == want => true,
+            Some(got) => {
+                let message = format!("{} should be {}, but is {}", what, want, got);
+                self.warn(span, message);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// A branch of an `if` for a warning: its source if it's a literal.
+    fn described(&self, ty: Ty, effect: Effect) -> String {
+        match ty {
+            Ty::Block(_, Some(span)) => format!("{} {}", self.text(span), effect),
+            Ty::Block(..) => format!("a block {}", effect),
+            _ => format!("a value {}", effect),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effect(takes: usize, leaves: usize) -> Option<Effect> {
+        Some(Effect { takes, leaves })
+    }
+
+    fn warnings(source: &[u8]) -> Vec<String> {
+        let analysis = analyze(source).unwrap();
+        analysis.warnings.into_iter().map(|w| w.message).collect()
+    }
+
+    #[test]
+    fn works_out_effects() {
+        assert_eq!(analyze(b"1 2+").unwrap().program, effect(0, 1));
+        assert_eq!(analyze(b"+").unwrap().program, effect(2, 1));
+        assert_eq!(analyze(b"\\;").unwrap().program, effect(2, 1));
+        assert_eq!(analyze(b"[1 2 3]").unwrap().program, effect(0, 1));
+        let analysis = analyze(b"{1+}:inc;3 inc").unwrap();
+        assert_eq!(analysis.blocks[0].1, effect(1, 1));
+        assert_eq!(analysis.program, effect(0, 1));
+    }
+
+    #[test]
+    fn if_branches() {
+        assert_eq!(analyze(b"{1}{2}if").unwrap().program, effect(1, 1));
+        assert_eq!(analyze(b"1 2if").unwrap().program, effect(1, 1));
+        assert_eq!(
+            warnings(b"{1}{}if"),
+            ["`if` branches leave different numbers of values: {1} (0 -- 1), {} (0 -- 0)"]
+        );
+    }
+
+    #[test]
+    fn if_branch_of_unknown_type_is_unknown() {
+        // either branch may be a block, which runs
+        let analysis = analyze(b".1if").unwrap();
+        assert_eq!(analysis.program, None);
+        assert!(analysis.warnings.is_empty());
+        assert_eq!(analyze(b"{1}if").unwrap().program, None);
+        assert_eq!(analyze(b"2 1if").unwrap().program, effect(1, 1));
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(analyze(b"{1-.}do").unwrap().program, effect(1, 1));
+        assert_eq!(
+            warnings(b"{1 2}do"),
+            ["`do` body should leave one more value than it takes, for the condition, but is (0 -- 2)"]
+        );
+        assert_eq!(
+            warnings(b"{.}{1}while"),
+            ["`while` body should leave as many values as it takes, but is (0 -- 1)"]
+        );
+        assert_eq!(
+            warnings(b"[1 2]{+ +}*"),
+            ["fold block should be (2 -- 1), but is (3 -- 1)"]
+        );
+    }
+
+    #[test]
+    fn map_blocks() {
+        assert!(warnings(b"[1 2]{2+}%").is_empty());
+        assert_eq!(
+            warnings(b"[1 2]{+}%"),
+            ["map block takes 2 values, but only its element is there to take"]
+        );
+        assert_eq!(
+            warnings(b"[1 2]{(}%"),
+            ["map block's effect depends on the values it's given, so how many it takes can't be checked"]
+        );
+    }
+
+    #[test]
+    fn other_block_builtins() {
+        assert_eq!(
+            warnings(b"[1 2]{;}$"),
+            ["`$` block should be (1 -- 1), but is (1 -- 0)"]
+        );
+        assert_eq!(
+            warnings(b"1{1 2}and"),
+            ["`and` block should be (0 -- 1), but is (0 -- 2)"]
+        );
+    }
+
+    #[test]
+    fn builtins_on_unknown_types_are_unknown() {
+        // either value may be a block, which runs
+        for source in [&b"..and"[..], b"..or", b"..*", b".1*", b"{1}1or"] {
+            let analysis = analyze(source).unwrap();
+            assert_eq!(analysis.program, None, "{}", source.escape_ascii());
+            assert!(analysis.warnings.is_empty());
+        }
+        assert_eq!(analyze(b"1 2or").unwrap().program, effect(0, 1));
+        assert_eq!(analyze(b"2 3*").unwrap().program, effect(0, 1));
+    }
+}
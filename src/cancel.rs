@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Stops a running interpreter from another thread.
+///
+/// Get one with [`Gs::cancel_token`](crate::Gs::cancel_token). Clones share
+/// the same flag, so a host can hand one to a watchdog thread or a UI and
+/// keep the interpreter on its own thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Asks the interpreter to stop. A run in progress fails with
+    /// [`ErrorKind::Cancelled`](crate::ErrorKind::Cancelled) at its next
+    /// step; if none is in progress, the next one does.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears a cancellation, reporting whether there was one.
+    pub(crate) fn take(&self) -> bool {
+        self.is_cancelled() && self.0.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,272 @@
+use crate::int::Int;
+use crate::parse::{parse_code, Gtoken, Span, Token};
+use crate::unescape::unescape;
+use crate::value::{Block, Gval};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A parsed program or block body, ready to run without touching its source
+/// again.
+#[derive(Debug)]
+pub(crate) struct Code {
+    pub instrs: Vec<Instr>,
+    /// The [`Gs::run`](crate::Gs::run) call whose source the spans refer to,
+    /// or `None` for code built at runtime, whose spans are only meaningful
+    /// relative to itself.
+    pub origin: Option<u64>,
+    /// The [`Symbols`] table the instructions' ids come from.
+    pub table: u64,
+    /// The block literal this is the body of, when compiled along with the
+    /// code around it.
+    pub span: Option<Span>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Instr {
+    /// The token as written.
+    pub lexeme: Vec<u8>,
+    /// The interned lexeme. Any token can be used as a variable name, so
+    /// this is looked up in the variables before `op` runs.
+    pub symbol: Symbol,
+    pub span: Span,
+    pub op: Op,
+}
+
+#[derive(Debug)]
+pub(crate) enum Op {
+    /// An int or string literal, evaluated once at compile time.
+    Push(Gval),
+    /// A block literal, whose body has been compiled along with its parent.
+    Block(Block),
+    /// A builtin, unless a variable of the same name shadows it.
+    Builtin(Builtin),
+    /// Any other identifier: a variable reference, or nothing if unassigned.
+    Symbol,
+    /// `:name`, or a trailing `:` with nothing to assign to.
+    Assign(Option<Symbol>),
+}
+
+/// An interned token, usable as an index into an interpreter's variables.
+pub(crate) type Symbol = usize;
+
+/// The symbols an interpreter has seen, each mapped to a dense id.
+#[derive(Debug)]
+pub(crate) struct Symbols {
+    /// Tells tables apart, so code compiled against one interpreter's table
+    /// is never run with another's ids.
+    table: u64,
+    ids: HashMap<Vec<u8>, Symbol>,
+    names: Vec<Vec<u8>>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        static TABLES: AtomicU64 = AtomicU64::new(0);
+        Symbols {
+            table: TABLES.fetch_add(1, Ordering::Relaxed),
+            ids: HashMap::new(),
+            names: vec![],
+        }
+    }
+
+    pub fn table(&self) -> u64 {
+        self.table
+    }
+
+    pub fn intern(&mut self, name: &[u8]) -> Symbol {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.ids.insert(name.to_vec(), id);
+        self.names.push(name.to_vec());
+        id
+    }
+
+    /// The id of `name`, if it was ever interned.
+    pub fn get(&self, name: &[u8]) -> Option<Symbol> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: Symbol) -> &[u8] {
+        &self.names[id]
+    }
+}
+
+/// The builtins, resolved from their names at compile time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Builtin {
+    Tilde,
+    Backtick,
+    Bang,
+    AtSign,
+    Dollar,
+    Plus,
+    Minus,
+    Asterisk,
+    Slash,
+    Percent,
+    VerticalBar,
+    Ampersand,
+    Caret,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    Semicolon,
+    Less,
+    Equal,
+    Greater,
+    Comma,
+    Dot,
+    Question,
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Xor,
+    N,
+    Print,
+    P,
+    Puts,
+    Rand,
+    Do,
+    While,
+    Until,
+    If,
+    Abs,
+    Zip,
+    Base,
+    Gets,
+    Read,
+}
+
+impl Builtin {
+    pub fn from_name(name: &[u8]) -> Option<Builtin> {
+        use Builtin::*;
+        Some(match name {
+            b"~" => Tilde,
+            b"`" => Backtick,
+            b"!" => Bang,
+            b"@" => AtSign,
+            b"$" => Dollar,
+            b"+" => Plus,
+            b"-" => Minus,
+            b"*" => Asterisk,
+            b"/" => Slash,
+            b"%" => Percent,
+            b"|" => VerticalBar,
+            b"&" => Ampersand,
+            b"^" => Caret,
+            b"[" => LeftBracket,
+            b"]" => RightBracket,
+            b"\\" => Backslash,
+            b";" => Semicolon,
+            b"<" => Less,
+            b"=" => Equal,
+            b">" => Greater,
+            b"," => Comma,
+            b"." => Dot,
+            b"?" => Question,
+            b"(" => LeftParen,
+            b")" => RightParen,
+            b"and" => And,
+            b"or" => Or,
+            b"xor" => Xor,
+            b"n" => N,
+            b"print" => Print,
+            b"p" => P,
+            b"puts" => Puts,
+            b"rand" => Rand,
+            b"do" => Do,
+            b"while" => While,
+            b"until" => Until,
+            b"if" => If,
+            b"abs" => Abs,
+            b"zip" => Zip,
+            b"base" => Base,
+            b"gets" => Gets,
+            b"read" => Read,
+            _ => return None,
+        })
+    }
+}
+
+/// Why source didn't compile: a message and the byte offset it refers to.
+#[derive(Debug)]
+pub(crate) struct CompileError {
+    pub message: &'static str,
+    pub offset: usize,
+}
+
+pub(crate) fn compile(
+    src: &[u8],
+    origin: Option<u64>,
+    symbols: &mut Symbols,
+) -> Result<Code, CompileError> {
+    Ok(lower(tokenize(src)?, origin, None, symbols))
+}
+
+/// Tokenizes all of `src`, which must not stop inside a block or string.
+pub(crate) fn tokenize(src: &[u8]) -> Result<Vec<Token<'_>>, CompileError> {
+    let (rest, tokens) = parse_code(src).map_err(|_| CompileError {
+        message: "invalid token",
+        offset: 0,
+    })?;
+    if !rest.is_empty() {
+        let message = match rest[0] {
+            b'}' => "unmatched `}`",
+            b'{' => "unterminated block",
+            _ => "unterminated string",
+        };
+        return Err(CompileError {
+            message,
+            offset: src.len() - rest.len(),
+        });
+    }
+    Ok(tokens)
+}
+
+fn lower(
+    tokens: Vec<Token>,
+    origin: Option<u64>,
+    span: Option<Span>,
+    symbols: &mut Symbols,
+) -> Code {
+    let mut instrs = vec![];
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let lexeme = token.gtoken.lexeme().to_vec();
+        let symbol = symbols.intern(&lexeme);
+        let op = match token.gtoken {
+            Gtoken::Symbol(b":") => Op::Assign(
+                tokens
+                    .next()
+                    .map(|name| symbols.intern(name.gtoken.lexeme())),
+            ),
+            Gtoken::IntLiteral(bs) => Op::Push(Gval::Int(Int::parse(bs).unwrap())),
+            Gtoken::SingleQuotedString(bs) => Op::Push(unescape(bs, true).into()),
+            Gtoken::DoubleQuotedString(bs) => Op::Push(unescape(bs, false).into()),
+            Gtoken::Block(inner, src) => {
+                let body = lower(inner, origin, Some(token.span), symbols);
+                Op::Block(Block::compiled(src.to_vec(), body))
+            }
+            Gtoken::Symbol(name) => match Builtin::from_name(name) {
+                Some(builtin) => Op::Builtin(builtin),
+                None => Op::Symbol,
+            },
+            Gtoken::Comment(_) => continue,
+        };
+        instrs.push(Instr {
+            lexeme,
+            symbol,
+            span: token.span,
+            op,
+        });
+    }
+    Code {
+        instrs,
+        origin,
+        table: symbols.table(),
+        span,
+    }
+}
@@ -1,12 +1,13 @@
+use crate::int::Int;
 use crate::util::to_byte;
 use crate::value::Gval;
-use num::BigInt;
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub enum Coerced {
-    Ints(BigInt, BigInt),
-    Arrs(Vec<Gval>, Vec<Gval>),
-    Strs(Vec<u8>, Vec<u8>),
+    Ints(Int, Int),
+    Arrs(Rc<Vec<Gval>>, Rc<Vec<Gval>>),
+    Strs(Rc<Vec<u8>>, Rc<Vec<u8>>),
     Blks(Vec<u8>, Vec<u8>),
 }
 
@@ -16,7 +17,7 @@ impl Coerced {
             Coerced::Ints(a, _) => Gval::Int(a),
             Coerced::Arrs(a, _) => Gval::Arr(a),
             Coerced::Strs(a, _) => Gval::Str(a),
-            Coerced::Blks(a, _) => Gval::Blk(a),
+            Coerced::Blks(a, _) => Gval::Blk(a.into()),
         }
     }
 }
@@ -25,25 +26,28 @@ pub fn flatten_append(bytes: &mut Vec<u8>, val: Gval) {
     match val {
         Gval::Int(a) => bytes.push(to_byte(a)),
         Gval::Arr(vs) => {
-            for v in vs {
+            for v in Rc::unwrap_or_clone(vs) {
                 flatten_append(bytes, v);
             }
         }
-        Gval::Str(bs) | Gval::Blk(bs) => bytes.extend(bs),
+        Gval::Str(bs) => bytes.extend_from_slice(&bs),
+        Gval::Blk(b) => bytes.extend_from_slice(b.source()),
     }
 }
 
 pub fn flatten(arr: Vec<Gval>) -> Vec<u8> {
     let mut bytes: Vec<u8> = vec![];
-    flatten_append(&mut bytes, Gval::Arr(arr));
+    for v in arr {
+        flatten_append(&mut bytes, v);
+    }
     bytes
 }
 
-fn show_words(arr: Vec<Gval>) -> Vec<u8> {
+fn show_words(arr: Rc<Vec<Gval>>) -> Vec<u8> {
     let mut bytes: Vec<u8> = vec![];
-    for (i, v) in arr.into_iter().enumerate() {
+    for (i, v) in Rc::unwrap_or_clone(arr).into_iter().enumerate() {
         if i > 0 {
-            bytes.push(' ' as u8)
+            bytes.push(b' ')
         }
         bytes.extend(v.to_gs())
     }
@@ -57,22 +61,22 @@ pub fn coerce(a: Gval, b: Gval) -> Coerced {
         (Int(a), Int(b)) => Coerced::Ints(a, b),
         (Arr(a), Arr(b)) => Coerced::Arrs(a, b),
         (Str(a), Str(b)) => Coerced::Strs(a, b),
-        (Blk(a), Blk(b)) => Coerced::Blks(a, b),
-        (Str(a), Blk(b)) => Coerced::Blks(a, b),
-        (Blk(a), Str(b)) => Coerced::Blks(a, b),
+        (Blk(a), Blk(b)) => Coerced::Blks(a.into_source(), b.into_source()),
+        (Str(a), Blk(b)) => Coerced::Blks(Rc::unwrap_or_clone(a), b.into_source()),
+        (Blk(a), Str(b)) => Coerced::Blks(a.into_source(), Rc::unwrap_or_clone(b)),
         // int + arr: wrap the int
-        (Int(a), Arr(b)) => Coerced::Arrs(vec![Int(a)], b),
-        (Arr(a), Int(b)) => Coerced::Arrs(a, vec![Int(b)]),
+        (Int(a), Arr(b)) => Coerced::Arrs(Rc::new(vec![Int(a)]), b),
+        (Arr(a), Int(b)) => Coerced::Arrs(a, Rc::new(vec![Int(b)])),
         // int + str/blk: show the int
-        (Int(a), Str(b)) => Coerced::Strs(a.to_str_radix(10).into_bytes(), b),
-        (Str(a), Int(b)) => Coerced::Strs(a, b.to_str_radix(10).into_bytes()),
-        (Int(a), Blk(b)) => Coerced::Blks(a.to_str_radix(10).into_bytes(), b),
-        (Blk(a), Int(b)) => Coerced::Blks(a, b.to_str_radix(10).into_bytes()),
+        (Int(a), Str(b)) => Coerced::Strs(Rc::new(a.to_string().into_bytes()), b),
+        (Str(a), Int(b)) => Coerced::Strs(a, Rc::new(b.to_string().into_bytes())),
+        (Int(a), Blk(b)) => Coerced::Blks(a.to_string().into_bytes(), b.into_source()),
+        (Blk(a), Int(b)) => Coerced::Blks(a.into_source(), b.to_string().into_bytes()),
         // str + arr: flatten the arr
-        (Arr(a), Str(b)) => Coerced::Strs(flatten(a), b),
-        (Str(a), Arr(b)) => Coerced::Strs(a, flatten(b)),
+        (Arr(a), Str(b)) => Coerced::Strs(Rc::new(flatten(Rc::unwrap_or_clone(a))), b),
+        (Str(a), Arr(b)) => Coerced::Strs(a, Rc::new(flatten(Rc::unwrap_or_clone(b)))),
         // arr + blk: show arr contents space-separated
-        (Arr(a), Blk(b)) => Coerced::Blks(show_words(a), b),
-        (Blk(a), Arr(b)) => Coerced::Blks(a, show_words(b)),
+        (Arr(a), Blk(b)) => Coerced::Blks(show_words(a), b.into_source()),
+        (Blk(a), Arr(b)) => Coerced::Blks(a.into_source(), show_words(b)),
     }
 }
@@ -0,0 +1,188 @@
+use crate::gs::{Gs, Hook, Location};
+use crate::parse::{parse_code, Gtoken, Span, Token};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A [`Hook`] that counts how often each token of a program ran, and each
+/// of its blocks, to find code that never runs.
+///
+/// Use the same `Coverage` for several runs of the program, on different
+/// inputs, to add their counts up.
+pub struct Coverage {
+    source: Vec<u8>,
+    /// The tokens that do something, in source order: not whitespace or
+    /// comments, and not the names after `:`, which belong to the `:`.
+    tokens: Vec<Span>,
+    /// The block literals, in source order.
+    blocks: Vec<Span>,
+    ran: HashMap<Span, u64>,
+    entered: HashMap<Span, u64>,
+}
+
+impl Coverage {
+    /// Coverage of `source`, which is what the runs should run.
+    pub fn new(source: impl Into<Vec<u8>>) -> Coverage {
+        let source = source.into();
+        let mut tokens = vec![];
+        let mut blocks = vec![];
+        if let Ok((_, parsed)) = parse_code(&source) {
+            collect(&parsed, &mut tokens, &mut blocks);
+        }
+        Coverage {
+            source,
+            tokens,
+            blocks,
+            ran: HashMap::new(),
+            entered: HashMap::new(),
+        }
+    }
+
+    /// Every token that does something, with how often it ran. A block
+    /// literal's count is how often it was pushed.
+    pub fn tokens(&self) -> Vec<(Span, u64)> {
+        let count = |s: &Span| (*s, self.ran.get(s).copied().unwrap_or(0));
+        self.tokens.iter().map(count).collect()
+    }
+
+    /// Every block literal, with how often it was called.
+    pub fn blocks(&self) -> Vec<(Span, u64)> {
+        let count = |s: &Span| (*s, self.entered.get(s).copied().unwrap_or(0));
+        self.blocks.iter().map(count).collect()
+    }
+
+    /// The count for each line: the most any token on it ran, or `None` if
+    /// it has no tokens.
+    fn lines(&self) -> Vec<Option<u64>> {
+        let lines = self.source.split(|&b| b == b'\n').count();
+        let mut counts = vec![None; lines];
+        for (span, n) in self.tokens() {
+            let count = &mut counts[span.line - 1];
+            *count = Some(count.unwrap_or(0).max(n));
+        }
+        counts
+    }
+
+    /// The source with each line's count in the margin, `#####` for lines
+    /// that never ran, and the tokens that never ran marked under lines that
+    /// partly did. Ends with a summary.
+    pub fn listing(&self) -> String {
+        let tokens = self.tokens();
+        let mut listing = String::new();
+        for (i, (line, count)) in self
+            .source
+            .split(|&b| b == b'\n')
+            .zip(self.lines())
+            .enumerate()
+        {
+            let margin = match count {
+                None => "-".to_owned(),
+                Some(0) => "#####".to_owned(),
+                Some(n) => n.to_string(),
+            };
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let _ = writeln!(
+                listing,
+                "{:>9}:{:>5}: {}",
+                margin,
+                i + 1,
+                String::from_utf8_lossy(line)
+            );
+            if count.is_some_and(|n| n > 0) {
+                let mut marks = vec![b' '; line.len()];
+                let unrun = tokens.iter().filter(|t| t.0.line == i + 1 && t.1 == 0);
+                for (span, _) in unrun {
+                    // a token can run past the end of its line
+                    let end = (span.col - 1 + span.end - span.start).min(line.len());
+                    marks[span.col - 1..end].fill(b'^');
+                }
+                if marks.contains(&b'^') {
+                    let marks = String::from_utf8_lossy(&marks);
+                    let _ = writeln!(listing, "{:>9}:{:>5}: {}", "", "", marks.trim_end());
+                }
+            }
+        }
+        let run = tokens.iter().filter(|t| t.1 > 0).count();
+        let blocks = self.blocks();
+        let called = blocks.iter().filter(|b| b.1 > 0).count();
+        let _ = writeln!(
+            listing,
+            "tokens run: {}/{} ({:.1}%), blocks called: {}/{}",
+            run,
+            tokens.len(),
+            percent(run, tokens.len()),
+            called,
+            blocks.len()
+        );
+        listing
+    }
+
+    /// The counts in the lcov tracefile format, for `path`, the name of the
+    /// source file. Blocks are reported as functions named after where they
+    /// start.
+    pub fn lcov(&self, path: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", path);
+        let blocks = self.blocks();
+        for (span, _) in &blocks {
+            let _ = writeln!(lcov, "FN:{},block@{}:{}", span.line, span.line, span.col);
+        }
+        for (span, n) in &blocks {
+            let _ = writeln!(lcov, "FNDA:{},block@{}:{}", n, span.line, span.col);
+        }
+        let called = blocks.iter().filter(|b| b.1 > 0).count();
+        let _ = writeln!(lcov, "FNF:{}\nFNH:{}", blocks.len(), called);
+        let lines: Vec<_> = self.lines().into_iter().enumerate().collect();
+        for (i, count) in &lines {
+            if let Some(n) = count {
+                let _ = writeln!(lcov, "DA:{},{}", i + 1, n);
+            }
+        }
+        let found = lines.iter().filter(|l| l.1.is_some()).count();
+        let hit = lines.iter().filter(|l| l.1.is_some_and(|n| n > 0)).count();
+        let _ = writeln!(lcov, "LF:{}\nLH:{}\nend_of_record", found, hit);
+        lcov
+    }
+}
+
+fn percent(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        100.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+/// Finds the tokens and blocks of `parsed` the way they are compiled.
+fn collect(parsed: &[Token], tokens: &mut Vec<Span>, blocks: &mut Vec<Span>) {
+    let mut parsed = parsed.iter();
+    while let Some(token) = parsed.next() {
+        match &token.gtoken {
+            Gtoken::Comment(_) => continue,
+            Gtoken::Symbol(s) if s.iter().all(u8::is_ascii_whitespace) => continue,
+            Gtoken::Symbol(b":") => {
+                parsed.next();
+            }
+            Gtoken::Block(inner, _) => {
+                tokens.push(token.span);
+                blocks.push(token.span);
+                collect(inner, tokens, blocks);
+                continue;
+            }
+            _ => {}
+        }
+        tokens.push(token.span);
+    }
+}
+
+impl Hook for Coverage {
+    fn before(&mut self, _gs: &Gs, token: &Location) {
+        if let Some(span) = token.span {
+            *self.ran.entry(span).or_default() += 1;
+        }
+    }
+
+    fn enter(&mut self, _gs: &Gs, block: Option<Span>, _depth: usize) {
+        if let Some(span) = block {
+            *self.entered.entry(span).or_default() += 1;
+        }
+    }
+}
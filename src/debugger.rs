@@ -0,0 +1,332 @@
+use crate::error::GsError;
+use crate::gs::{Config, Gs, Location, Snapshot};
+use crate::parse::Span;
+use crate::value::Gval;
+
+/// Where [`Debugger::run`] stops.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the token covering this byte offset of the source.
+    Offset(usize),
+    /// Before any token that runs or assigns the variable with this name.
+    Var(Vec<u8>),
+}
+
+/// Runs a program on an input a token at a time, descending into the blocks
+/// and strings it runs, for stepping through it in an editor.
+///
+/// The debugger can also step backward, or jump to any earlier step. It
+/// keeps snapshots of the interpreter every so many steps and replays from
+/// the nearest one; the snapshots are thinned out to stay within
+/// [`Debugger::set_history_budget`]. Output sent to a sink that writes it
+/// through can't be taken back, so going back is only exact for output
+/// collected in memory, which is what a debugger has by default.
+///
+/// ```
+/// use golfscript_rs::{Breakpoint, Debugger, Gval};
+///
+/// let mut dbg = Debugger::new("", "1 2+:x;x 3*")?;
+/// dbg.add_breakpoint(Breakpoint::Var(b"x".to_vec()));
+/// assert!(dbg.run()?); // stopped before `:x`
+/// assert_eq!(dbg.stack(), [Gval::from(""), Gval::from(3)]);
+/// assert_eq!(dbg.span().map(|s| s.start), Some(4));
+/// while dbg.step()? {}
+/// assert_eq!(dbg.stack(), [Gval::from(""), Gval::from(9)]);
+/// # Ok::<(), golfscript_rs::GsError>(())
+/// ```
+pub struct Debugger {
+    gs: Gs,
+    config: Config,
+    input: Vec<u8>,
+    source: Vec<u8>,
+    breakpoints: Vec<Breakpoint>,
+    /// Steps taken since the start.
+    position: usize,
+    history: History,
+}
+
+/// Snapshots of a run, at every `interval`th step.
+struct History {
+    checkpoints: Vec<(usize, Snapshot)>,
+    interval: usize,
+    bytes: usize,
+    budget: usize,
+}
+
+impl History {
+    fn new(budget: usize) -> History {
+        History {
+            checkpoints: vec![],
+            interval: 1,
+            bytes: 0,
+            budget,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.interval = 1;
+        self.bytes = 0;
+    }
+
+    /// Keeps a snapshot of step `position`, if it's a checkpoint not yet
+    /// taken.
+    fn record(&mut self, position: usize, gs: &Gs) {
+        let taken = self.checkpoints.last().is_some_and(|c| c.0 >= position);
+        if taken || !position.is_multiple_of(self.interval) {
+            return;
+        }
+        let snapshot = gs.snapshot();
+        self.bytes += snapshot.size();
+        self.checkpoints.push((position, snapshot));
+        self.thin();
+    }
+
+    /// Spaces the checkpoints twice as far apart until they fit the budget.
+    /// The first one always stays, so there is somewhere to replay from.
+    fn thin(&mut self) {
+        while self.bytes > self.budget && self.checkpoints.len() > 1 {
+            self.interval *= 2;
+            let interval = self.interval;
+            self.checkpoints.retain(|c| c.0.is_multiple_of(interval));
+            self.bytes = self.checkpoints.iter().map(|c| c.1.size()).sum();
+        }
+    }
+
+    /// The latest checkpoint at or before step `position`.
+    fn before(&self, position: usize) -> Option<&(usize, Snapshot)> {
+        self.checkpoints.iter().rev().find(|c| c.0 <= position)
+    }
+}
+
+impl Debugger {
+    /// A debugger stopped before the first token of `source`, with `input`
+    /// on the stack, using the strict configuration.
+    pub fn new(input: impl Into<Vec<u8>>, source: impl Into<Vec<u8>>) -> Result<Self, GsError> {
+        Debugger::with_config(Config::strict(), input, source)
+    }
+
+    pub fn with_config(
+        config: Config,
+        input: impl Into<Vec<u8>>,
+        source: impl Into<Vec<u8>>,
+    ) -> Result<Self, GsError> {
+        let mut dbg = Debugger {
+            gs: Gs::with_config(config),
+            config,
+            input: input.into(),
+            source: source.into(),
+            breakpoints: vec![],
+            position: 0,
+            history: History::new(64 << 20),
+        };
+        dbg.reset()?;
+        Ok(dbg)
+    }
+
+    /// Starts over from the first token, keeping the breakpoints.
+    pub fn reset(&mut self) -> Result<(), GsError> {
+        self.gs = Gs::with_config(self.config);
+        self.gs.push(Gval::from(self.input.clone()));
+        self.position = 0;
+        self.history.clear();
+        self.gs.load(&self.source)?;
+        self.skip_whitespace()?;
+        self.history.record(0, &self.gs);
+        Ok(())
+    }
+
+    /// Runs one token. Returns whether anything is left to run.
+    pub fn step(&mut self) -> Result<bool, GsError> {
+        if self.finished() {
+            return Ok(false);
+        }
+        // a step that fails still counts, so stepping back lands before it
+        self.position += 1;
+        self.gs.step_token()?;
+        let more = self.skip_whitespace()?;
+        self.history.record(self.position, &self.gs);
+        Ok(more)
+    }
+
+    /// Undoes the last step. Returns whether there was one to undo.
+    pub fn step_back(&mut self) -> Result<bool, GsError> {
+        if self.position == 0 {
+            return Ok(false);
+        }
+        self.goto(self.position - 1)?;
+        Ok(true)
+    }
+
+    /// Goes to the state after `position` steps from the start, or to the
+    /// end if the program finishes sooner. Returns whether anything is left
+    /// to run.
+    pub fn goto(&mut self, position: usize) -> Result<bool, GsError> {
+        if position < self.position {
+            match self.history.before(position) {
+                Some((at, snapshot)) => {
+                    self.position = *at;
+                    self.gs.restore(snapshot.clone());
+                }
+                None => self.reset()?,
+            }
+        }
+        while self.position < position && !self.finished() {
+            self.step()?;
+        }
+        Ok(!self.finished())
+    }
+
+    /// How many steps have run since the start.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Bounds the memory the snapshots for going back may take, roughly,
+    /// in bytes. The default is 64 MiB. A smaller budget makes going back
+    /// replay more steps.
+    pub fn set_history_budget(&mut self, bytes: usize) {
+        self.history.budget = bytes;
+        self.history.thin();
+    }
+
+    /// Whitespace is a token, and runs code if it was assigned, but it isn't
+    /// worth stopping at.
+    fn skip_whitespace(&mut self) -> Result<bool, GsError> {
+        while let Some(loc) = self.location() {
+            if !loc.lexeme.iter().all(u8::is_ascii_whitespace) {
+                return Ok(true);
+            }
+            self.gs.step_token()?;
+        }
+        Ok(false)
+    }
+
+    /// Runs until the next breakpoint or the end. Returns whether it stopped
+    /// at a breakpoint.
+    pub fn run(&mut self) -> Result<bool, GsError> {
+        // always move on, so running from a breakpoint doesn't stop at it
+        while self.step()? {
+            if self.at_breakpoint() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Whether the program has run to the end, or stopped with an error.
+    pub fn finished(&self) -> bool {
+        self.gs.next_token().is_none()
+    }
+
+    /// The token the next step runs.
+    pub fn location(&self) -> Option<Location<'_>> {
+        self.gs.next_token()
+    }
+
+    /// The span of the token the next step runs, if it is part of the
+    /// source rather than code built while running.
+    pub fn span(&self) -> Option<Span> {
+        self.location()?.span
+    }
+
+    pub fn stack(&self) -> &[Gval] {
+        self.gs.stack()
+    }
+
+    /// The interpreter, for its variables and output.
+    pub fn gs(&self) -> &Gs {
+        &self.gs
+    }
+
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes `breakpoint`, returning whether it was set.
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| b != breakpoint);
+        self.breakpoints.len() != len
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let Some(loc) = self.location() else {
+            return false;
+        };
+        self.breakpoints.iter().any(|b| match b {
+            Breakpoint::Offset(offset) => loc
+                .span
+                .is_some_and(|s| (s.start..s.end.max(s.start + 1)).contains(offset)),
+            // a literal's lexeme can look like a name, as a block's source does
+            Breakpoint::Var(name) => (loc.name && loc.lexeme == name) || loc.assigns == Some(name),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_past_the_end_dont_count() {
+        let mut dbg = Debugger::new("", "1 2+").unwrap();
+        while dbg.step().unwrap() {}
+        let end = dbg.position();
+        assert_eq!(end, 3);
+        assert!(!dbg.step().unwrap());
+        assert!(!dbg.run().unwrap());
+        assert_eq!(dbg.position(), end);
+        assert!(dbg.step_back().unwrap());
+        assert_eq!(dbg.position(), end - 1);
+        assert_eq!(dbg.stack(), [Gval::from(""), Gval::from(1), Gval::from(2)]);
+    }
+
+    #[test]
+    fn var_breakpoints_skip_literals() {
+        let mut dbg = Debugger::new("", "{x}'x'1:x;{x p}~").unwrap();
+        dbg.add_breakpoint(Breakpoint::Var(b"x".to_vec()));
+        assert!(dbg.run().unwrap());
+        assert_eq!(dbg.location().unwrap().lexeme, b":");
+        assert!(dbg.run().unwrap());
+        let loc = dbg.location().unwrap();
+        assert_eq!((loc.lexeme, loc.depth), (&b"x"[..], 1));
+        assert!(!dbg.run().unwrap());
+    }
+
+    #[test]
+    fn lexemes() {
+        let mut dbg = Debugger::new("", "'a' \"b\" {c}").unwrap();
+        let mut lexemes = vec![dbg.location().unwrap().lexeme.to_vec()];
+        while dbg.step().unwrap() {
+            lexemes.push(dbg.location().unwrap().lexeme.to_vec());
+        }
+        assert_eq!(lexemes, [&b"'a'"[..], b"\"b\"", b"c"]);
+    }
+
+    #[test]
+    fn lowering_the_budget_thins_the_history() {
+        let mut dbg = Debugger::new("", "100,{.}%").unwrap();
+        while dbg.step().unwrap() {}
+        let before = dbg.history.checkpoints.len();
+        assert!(before > 1);
+        dbg.set_history_budget(dbg.history.bytes / 4);
+        assert!(dbg.history.bytes <= dbg.history.budget || dbg.history.checkpoints.len() == 1);
+        assert!(dbg.history.checkpoints.len() < before);
+        assert_eq!(dbg.history.checkpoints[0].0, 0);
+        // going back still works, by replaying more
+        let end = dbg.position();
+        dbg.goto(end / 2).unwrap();
+        assert_eq!(dbg.position(), end / 2);
+    }
+}
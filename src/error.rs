@@ -0,0 +1,234 @@
+use crate::parse::Span;
+use std::fmt;
+
+/// Why a GolfScript program stopped, and where.
+///
+/// Lenient interpreters recover instead of returning errors, so these are
+/// seen in strict mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GsError {
+    pub kind: ErrorKind,
+    /// The failing token, in the coordinates of the source passed to
+    /// [`Gs::run`](crate::Gs::run). When the failure happened inside code
+    /// that wasn't part of that source, such as a block built at runtime,
+    /// this is the token of the source that ran it.
+    pub span: Option<Span>,
+}
+
+/// The kinds of [`GsError`].
+///
+/// `op` fields hold the lexeme of the token that failed, e.g. `"/"` or
+/// `"zip"`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An operator needed more values than the stack held.
+    StackUnderflow { op: String },
+    /// An operator doesn't accept these operand types, listed bottom first.
+    TypeMismatch {
+        op: String,
+        types: Vec<&'static str>,
+    },
+    /// Division, modulo, chunking, stepping or splitting by zero or by an
+    /// empty separator.
+    DivisionByZero { op: String },
+    /// `(` or `)` applied to an empty array, string or block.
+    EmptyValue { op: String },
+    /// The source didn't parse.
+    Parse { message: String },
+    /// The program ran into one of the interpreter's limits.
+    LimitExceeded { op: String, limit: Limit },
+    /// The run was stopped with a [`CancelToken`](crate::CancelToken). The
+    /// stack is left as it was, except for the operands of a builtin that
+    /// was interrupted midway.
+    Cancelled { op: String },
+    /// The output sink failed to take the program's output.
+    Output { op: String, message: String },
+    /// Reading streamed input failed.
+    Input { op: String, message: String },
+}
+
+/// The resource a [`ErrorKind::LimitExceeded`] ran out of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// A single loop reached [`Config::max_loops`](crate::Config::max_loops).
+    Loop,
+    /// A lenient run refused to compute a huge power.
+    Power,
+    /// [`Limits::steps`](crate::Limits::steps)
+    Steps,
+    /// [`Limits::time`](crate::Limits::time)
+    Time,
+    /// [`Limits::heap`](crate::Limits::heap)
+    Heap,
+    /// [`Limits::depth`](crate::Limits::depth)
+    Depth,
+    /// [`Limits::stack`](crate::Limits::stack)
+    Stack,
+    /// [`Limits::output`](crate::Limits::output)
+    Output,
+}
+
+impl GsError {
+    pub fn new(kind: ErrorKind) -> GsError {
+        GsError { kind, span: None }
+    }
+
+    pub(crate) fn underflow() -> GsError {
+        GsError::new(ErrorKind::StackUnderflow { op: String::new() })
+    }
+
+    pub(crate) fn type_mismatch(op: &str, types: &[&'static str]) -> GsError {
+        GsError::new(ErrorKind::TypeMismatch {
+            op: op.to_owned(),
+            types: types.to_vec(),
+        })
+    }
+
+    pub(crate) fn division_by_zero(op: &str) -> GsError {
+        GsError::new(ErrorKind::DivisionByZero { op: op.to_owned() })
+    }
+
+    pub(crate) fn output(e: std::io::Error) -> GsError {
+        GsError::new(ErrorKind::Output {
+            op: String::new(),
+            message: e.to_string(),
+        })
+    }
+
+    pub(crate) fn input(e: std::io::Error) -> GsError {
+        GsError::new(ErrorKind::Input {
+            op: String::new(),
+            message: e.to_string(),
+        })
+    }
+
+    pub(crate) fn limit(limit: Limit) -> GsError {
+        GsError::new(ErrorKind::LimitExceeded {
+            op: String::new(),
+            limit,
+        })
+    }
+
+    /// The operator the error is attributed to.
+    pub fn op(&self) -> Option<&str> {
+        match &self.kind {
+            ErrorKind::StackUnderflow { op }
+            | ErrorKind::TypeMismatch { op, .. }
+            | ErrorKind::DivisionByZero { op }
+            | ErrorKind::EmptyValue { op }
+            | ErrorKind::LimitExceeded { op, .. }
+            | ErrorKind::Cancelled { op }
+            | ErrorKind::Output { op, .. }
+            | ErrorKind::Input { op, .. } => Some(op),
+            ErrorKind::Parse { .. } => None,
+        }
+    }
+
+    /// Attributes the error to the token `lexeme` at `span`, unless an inner
+    /// token already claimed it.
+    pub(crate) fn at(mut self, lexeme: &[u8], span: Option<Span>) -> GsError {
+        match &mut self.kind {
+            ErrorKind::StackUnderflow { op }
+            | ErrorKind::TypeMismatch { op, .. }
+            | ErrorKind::DivisionByZero { op }
+            | ErrorKind::EmptyValue { op }
+            | ErrorKind::LimitExceeded { op, .. }
+            | ErrorKind::Cancelled { op }
+            | ErrorKind::Output { op, .. }
+            | ErrorKind::Input { op, .. } => {
+                if op.is_empty() {
+                    *op = String::from_utf8_lossy(lexeme).into_owned();
+                }
+            }
+            ErrorKind::Parse { .. } => {}
+        }
+        if self.span.is_none() {
+            self.span = span;
+        }
+        self
+    }
+
+    /// The line of `source` containing the error with the failing token
+    /// underlined, e.g.
+    ///
+    /// ```text
+    /// 1 | 5 0/
+    ///   |    ^
+    /// ```
+    pub fn excerpt(&self, source: &[u8]) -> Option<String> {
+        let span = self.span?;
+        let line_start = span.start - (span.col - 1);
+        let line_end = source[line_start..]
+            .iter()
+            .position(|&b| b == b'\n' || b == b'\r')
+            .map_or(source.len(), |n| line_start + n);
+        let line = String::from_utf8_lossy(&source[line_start..line_end]);
+        let number = span.line.to_string();
+        let width = span.end.min(line_end).saturating_sub(span.start).max(1);
+        Some(format!(
+            "{} | {}\n{} | {}{}",
+            number,
+            line,
+            " ".repeat(number.len()),
+            " ".repeat(span.col - 1),
+            "^".repeat(width)
+        ))
+    }
+}
+
+impl From<ErrorKind> for GsError {
+    fn from(kind: ErrorKind) -> GsError {
+        GsError::new(kind)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::StackUnderflow { op } => write!(f, "stack underflow in `{}`", op),
+            ErrorKind::TypeMismatch { op, types } => {
+                write!(f, "`{}` can't be applied to {}", op, types.join(" and "))
+            }
+            ErrorKind::DivisionByZero { op } => write!(f, "division by zero in `{}`", op),
+            ErrorKind::EmptyValue { op } => write!(f, "`{}` of an empty value", op),
+            ErrorKind::Parse { message } => write!(f, "parse error: {}", message),
+            ErrorKind::LimitExceeded { op, limit } => {
+                write!(f, "{} limit exceeded in `{}`", limit, op)
+            }
+            ErrorKind::Cancelled { op } => write!(f, "cancelled in `{}`", op),
+            ErrorKind::Output { op, message } => {
+                write!(f, "can't write output in `{}`: {}", op, message)
+            }
+            ErrorKind::Input { op, message } => {
+                write!(f, "can't read input in `{}`: {}", op, message)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Loop => "loop",
+            Limit::Power => "power",
+            Limit::Steps => "step",
+            Limit::Time => "time",
+            Limit::Heap => "memory",
+            Limit::Depth => "depth",
+            Limit::Stack => "stack size",
+            Limit::Output => "output size",
+        })
+    }
+}
+
+impl fmt::Display for GsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = self.span {
+            write!(f, " at line {}, column {}", span.line, span.col)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GsError {}
@@ -0,0 +1,368 @@
+use crate::code::{tokenize, CompileError};
+use crate::error::{ErrorKind, GsError};
+use crate::parse::{parse_code, Gtoken, Span, Token};
+
+/// Blocks whose contents fit in this many bytes stay on one line.
+const INLINE: usize = 60;
+
+/// Lays `source` out for reading: a line per statement, ending at `;` or an
+/// assignment, and a level of indentation per block that doesn't fit on one
+/// line. Comments and single blank lines are kept.
+///
+/// A block's source is its value, which `p` prints and `if` tests for
+/// emptiness, so only the blocks that nothing can see are laid out: those
+/// run at once with `~`, or assigned and dropped with `:name;`, which only
+/// ever run. The others are copied as written. The result is checked to
+/// tokenize to the same tokens and blocks. Whitespace runs code if it is
+/// assigned, so a program that assigns it is refused.
+///
+/// ```
+/// let code = b"~:n;{.1>{.(f*}{;1}if}:f;n f # factorial";
+/// let formatted = golfscript_rs::format(code)?;
+/// assert_eq!(
+///     formatted,
+///     b"~ :n;\n{. 1 > {.(f*} {;1} if} :f;\nn f # factorial\n"
+/// );
+/// # Ok::<(), golfscript_rs::GsError>(())
+/// ```
+pub fn format(source: &[u8]) -> Result<Vec<u8>, GsError> {
+    let tokens = parse(source)?;
+    if let Some(span) = assigned_whitespace(&tokens) {
+        return Err(GsError {
+            kind: ErrorKind::Parse {
+                message: "`:` assigns whitespace, so the spacing can't change".to_owned(),
+            },
+            span: Some(span),
+        });
+    }
+    let mut layout = Layout::new(source, !assigns_hiders(&tokens));
+    layout.tokens(&tokens);
+    layout.end_line();
+    check(source, &layout.out, true)?;
+    Ok(layout.out)
+}
+
+/// Tokenizes `source`, reporting where it fails to.
+pub(crate) fn parse(source: &[u8]) -> Result<Vec<Token<'_>>, GsError> {
+    tokenize(source).map_err(|CompileError { message, offset }| GsError {
+        kind: ErrorKind::Parse {
+            message: message.to_owned(),
+        },
+        span: Some(Span::point(source, offset)),
+    })
+}
+
+/// The `:` of the first assignment to a whitespace token, if any.
+pub(crate) fn assigned_whitespace(tokens: &[Token]) -> Option<Span> {
+    assignment(tokens, &is_whitespace)
+}
+
+/// The `:` of the first assignment to a name that `assigns` accepts, if any.
+fn assignment(tokens: &[Token], assigns: &impl Fn(&[u8]) -> bool) -> Option<Span> {
+    let mut tokens = tokens.iter();
+    while let Some(token) = tokens.next() {
+        match &token.gtoken {
+            Gtoken::Symbol(b":") => match tokens.next().map(|t| &t.gtoken) {
+                Some(Gtoken::Symbol(s)) if assigns(s) => return Some(token.span),
+                Some(Gtoken::Block(inner, _)) => {
+                    if let Some(span) = assignment(inner, assigns) {
+                        return Some(span);
+                    }
+                }
+                _ => {}
+            },
+            Gtoken::Block(inner, _) => {
+                if let Some(span) = assignment(inner, assigns) {
+                    return Some(span);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether the block at `tokens[i]` only ever runs, so its source can't be
+/// seen: it is run with `~`, or assigned with `:name;`.
+pub(crate) fn hidden(tokens: &[Token], i: usize) -> bool {
+    let mut rest = tokens[i + 1..].iter().filter(|t| match &t.gtoken {
+        Gtoken::Symbol(s) => !is_whitespace(s),
+        Gtoken::Comment(_) => false,
+        _ => true,
+    });
+    match rest.next().map(|t| &t.gtoken) {
+        Some(Gtoken::Symbol(b"~")) => true,
+        Some(Gtoken::Symbol(b":")) => {
+            // the name comes straight after the `:`
+            let name = tokens[i + 1..]
+                .iter()
+                .skip_while(|t| !matches!(t.gtoken, Gtoken::Symbol(b":")))
+                .nth(1);
+            matches!(name.map(|t| &t.gtoken), Some(Gtoken::Symbol(n)) if *n != b";")
+                && rest
+                    .nth(1)
+                    .is_some_and(|t| matches!(t.gtoken, Gtoken::Symbol(b";")))
+        }
+        _ => false,
+    }
+}
+
+/// Whether the program, or a string in it, assigns `~` or `;`, which
+/// [`hidden`] relies on.
+pub(crate) fn assigns_hiders(tokens: &[Token]) -> bool {
+    let hider = |name: &[u8]| name == b"~" || name == b";";
+    assignment(tokens, &hider).is_some()
+        || strings(tokens).into_iter().any(|string| {
+            parse_code(string).is_ok_and(|(_, tokens)| assignment(&tokens, &hider).is_some())
+        })
+}
+
+/// The contents of the string literals of `tokens`.
+pub(crate) fn strings<'a>(tokens: &[Token<'a>]) -> Vec<&'a [u8]> {
+    let mut strings = vec![];
+    for token in tokens {
+        match &token.gtoken {
+            Gtoken::SingleQuotedString(s) | Gtoken::DoubleQuotedString(s) => {
+                strings.push(&s[1..s.len() - 1])
+            }
+            Gtoken::Block(inner, _) => strings.extend(self::strings(inner)),
+            _ => {}
+        }
+    }
+    strings
+}
+
+fn has_comment(tokens: &[Token]) -> bool {
+    tokens.iter().any(|t| match &t.gtoken {
+        Gtoken::Comment(_) => true,
+        Gtoken::Block(inner, _) => has_comment(inner),
+        _ => false,
+    })
+}
+
+pub(crate) fn is_whitespace(lexeme: &[u8]) -> bool {
+    lexeme.iter().all(u8::is_ascii_whitespace)
+}
+
+/// Checks that `rewritten` tokenizes like `source`, apart from whitespace
+/// and, unless `comments`, comments, outside the blocks that can be seen.
+pub(crate) fn check(source: &[u8], rewritten: &[u8], comments: bool) -> Result<(), GsError> {
+    let tokens = parse(source)?;
+    let hiding = !assigns_hiders(&tokens);
+    let before = shape(&tokens, comments, hiding);
+    let after = parse(rewritten).map(|tokens| shape(&tokens, comments, hiding));
+    if after.ok() != Some(before) {
+        return Err(GsError::new(ErrorKind::Parse {
+            message: "rewriting changed the tokens".to_owned(),
+        }));
+    }
+    Ok(())
+}
+
+/// The lexemes of `tokens` that mean something. A block that can be seen
+/// is its source, whitespace and all, as that is its value; the others are
+/// opened and closed around their contents.
+pub(crate) fn shape<'a>(tokens: &[Token<'a>], comments: bool, hiding: bool) -> Vec<&'a [u8]> {
+    let mut shape = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.gtoken {
+            Gtoken::Symbol(s) if is_whitespace(s) => {}
+            Gtoken::Comment(_) if !comments => {}
+            Gtoken::Block(inner, _) if hiding && hidden(tokens, i) => {
+                shape.push(&b"{"[..]);
+                shape.extend(self::shape(inner, comments, hiding));
+                shape.push(&b"}"[..]);
+            }
+            Gtoken::Block(_, src) => shape.extend([&b"{"[..], src, &b"}"[..]]),
+            gtoken => shape.push(gtoken.lexeme()),
+        }
+    }
+    shape
+}
+
+struct Layout<'a> {
+    source: &'a [u8],
+    /// Whether blocks that only run may be laid out; see [`hidden`].
+    hiding: bool,
+    out: Vec<u8>,
+    line: Vec<u8>,
+    indent: usize,
+    /// How many line breaks the source has since the last token.
+    breaks: usize,
+    /// Everything goes on one line, for a block that may fit on one.
+    flat: bool,
+}
+
+impl Layout<'_> {
+    fn new(source: &[u8], hiding: bool) -> Layout<'_> {
+        Layout {
+            source,
+            hiding,
+            out: vec![],
+            line: vec![],
+            indent: 0,
+            breaks: 0,
+            flat: false,
+        }
+    }
+
+    fn tokens(&mut self, all: &[Token]) {
+        let mut tokens = all.iter().enumerate();
+        while let Some((i, token)) = tokens.next() {
+            match &token.gtoken {
+                Gtoken::Symbol(s) if is_whitespace(s) => {
+                    self.breaks += s.iter().filter(|&&b| b == b'\n').count();
+                }
+                // a comment after a statement on its line stays on that line
+                Gtoken::Comment(c) if self.breaks == 0 && self.line.is_empty() => {
+                    if self.out.pop().is_some() {
+                        self.out.push(b' ');
+                    }
+                    self.out.extend_from_slice(c);
+                    self.out.push(b'\n');
+                }
+                Gtoken::Comment(c) => {
+                    self.word(c);
+                    self.end_line();
+                }
+                Gtoken::Symbol(b":") => {
+                    let mut assign = b":".to_vec();
+                    if let Some((_, name)) = tokens.next() {
+                        assign.extend_from_slice(&self.source[name.span.start..name.span.end]);
+                    }
+                    // keep `:x;` together
+                    let mut rest = tokens.clone();
+                    let next = rest
+                        .find(|(_, t)| !matches!(&t.gtoken, Gtoken::Symbol(s) if is_whitespace(s)));
+                    if next.is_some_and(|(_, t)| matches!(t.gtoken, Gtoken::Symbol(b";"))) {
+                        assign.push(b';');
+                        tokens = rest;
+                    }
+                    self.word(&assign);
+                    self.end_line();
+                }
+                Gtoken::Symbol(b";") => {
+                    self.word(b";");
+                    self.end_line();
+                }
+                Gtoken::Block(inner, _) if self.hiding && hidden(all, i) => self.block(inner),
+                Gtoken::Block(..) => self.word(&self.source[token.span.start..token.span.end]),
+                gtoken => self.word(gtoken.lexeme()),
+            }
+        }
+    }
+
+    fn block(&mut self, inner: &[Token]) {
+        if !has_comment(inner) {
+            let mut flat = Layout::new(self.source, self.hiding);
+            flat.flat = true;
+            flat.tokens(inner);
+            // a block copied as written may span lines
+            let fits = flat.line.len() <= INLINE && !flat.line.contains(&b'\n');
+            if fits || self.flat {
+                let mut word = b"{".to_vec();
+                word.append(&mut flat.line);
+                word.push(b'}');
+                self.word(&word);
+                return;
+            }
+        }
+        self.word(b"{");
+        self.end_line();
+        self.indent += 1;
+        self.tokens(inner);
+        self.end_line();
+        self.indent -= 1;
+        self.line.push(b'}');
+        self.breaks = 0;
+    }
+
+    /// Adds a token to the current line, or starts a new one where the
+    /// source did.
+    fn word(&mut self, word: &[u8]) {
+        if self.breaks > 0 && !self.flat {
+            self.end_line();
+            if self.breaks > 1 && !self.out.is_empty() {
+                self.out.push(b'\n');
+            }
+        }
+        if !self.line.is_empty() {
+            self.line.push(b' ');
+        }
+        self.breaks = 0;
+        self.line.extend_from_slice(word);
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.is_empty() && !self.flat {
+            self.out.extend(std::iter::repeat_n(b' ', 4 * self.indent));
+            self.out.append(&mut self.line);
+            self.out.push(b'\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golfscript_bytes;
+
+    fn runs_the_same(code: &[u8]) {
+        let formatted = format(code).unwrap();
+        assert_eq!(
+            golfscript_bytes(b"", &formatted),
+            golfscript_bytes(b"", code),
+            "{:?} formatted as {:?}",
+            code.escape_ascii().to_string(),
+            formatted.escape_ascii().to_string()
+        );
+    }
+
+    #[test]
+    fn keeps_blocks_as_written() {
+        assert_eq!(format(b"{ }{1}{2}if").unwrap(), b"{ } {1} {2} if\n");
+        assert_eq!(format(b"{1+}p").unwrap(), b"{1+} p\n");
+        assert_eq!(format(b"{1 #c\n}p").unwrap(), b"{1 #c\n} p\n");
+        runs_the_same(b"{ }{1}{2}if");
+        runs_the_same(b"{1+}p");
+        runs_the_same(b"{ {1  2}`}~p");
+    }
+
+    #[test]
+    fn lays_out_blocks_that_only_run() {
+        let code = b"{\"fizz\"\"buzz\"\"fizzbuzz\"\"fizzbuzzfizz\"\"fizzbuzzbuzz\"\"buzzfizz\"]p}:fb;fb{ 1  2+}~p";
+        assert_eq!(
+            format(code).unwrap(),
+            b"{\n    \"fizz\" \"buzz\" \"fizzbuzz\" \"fizzbuzzfizz\" \"fizzbuzzbuzz\" \"buzzfizz\" ] p\n} :fb;\nfb {1 2 +} ~ p\n"
+        );
+        runs_the_same(code);
+        runs_the_same(b"{{1 2}p 3}~{ 4 }:f;f");
+        assert_eq!(format(b"{1 #c\n}:f;").unwrap(), b"{\n    1 #c\n} :f;\n");
+        // `~` runs a block, but a block is also seen if it's given to `p`
+        assert_eq!(format(b"{ 1 }p{ 1 }~").unwrap(), b"{ 1 } p {1} ~\n");
+        // unless `~` is redefined
+        assert_eq!(format(b"{p}:~;{ 1 }~").unwrap(), b"{p} :~;\n{ 1 } ~\n");
+    }
+
+    #[test]
+    fn breaks_lines_after_statements() {
+        assert_eq!(
+            format(b"1:a;2 3+# sum\n\n\np").unwrap(),
+            b"1 :a;\n2 3 + # sum\n\np\n"
+        );
+    }
+
+    #[test]
+    fn check_compares_block_sources() {
+        assert!(check(b"{1 +}", b"{1 +}", true).is_ok());
+        assert!(check(b"1 +", b"1+", true).is_ok());
+        assert!(check(b"{1 +}", b"{1+}", true).is_err());
+        assert!(check(b"{ }", b"{}", true).is_err());
+        assert!(check(b"{1 +}~", b"{1+}~", true).is_ok());
+    }
+
+    #[test]
+    fn refuses_assigned_whitespace() {
+        assert!(format(b"{1}: ;1 2").is_err());
+    }
+}
@@ -0,0 +1,1943 @@
+use crate::cancel::CancelToken;
+use crate::code::{compile, Builtin, Code, CompileError, Instr, Op, Symbol, Symbols};
+use crate::coerce::flatten;
+use crate::coerce::{coerce, Coerced};
+use crate::error::{ErrorKind, GsError, Limit};
+use crate::int::Int;
+use crate::parse::Span;
+use crate::sink::Sink;
+use crate::util::chunk;
+use crate::util::every_nth;
+use crate::util::index;
+use crate::util::slice;
+use crate::util::split;
+use crate::util::string_index;
+use crate::util::{set_and, set_or, set_subtract, set_xor};
+use crate::value::join;
+use crate::value::{Block, Gval};
+use num::ToPrimitive;
+use std::cmp::Ordering;
+use std::io::{BufRead, Read};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// How the interpreter reacts to programs that go wrong.
+///
+/// The strict configuration (used by the binary) stops with a [`GsError`] on
+/// stack underflow, division by zero and operators applied to unsupported
+/// types. The lenient configuration (used by the `golfscript` library
+/// function) substitutes a default value instead, marks the run as unstable,
+/// and quietly ends any loop that reaches `max_loops` iterations.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Substitute defaults for errors instead of failing.
+    pub lenient: bool,
+    /// Upper bound on the iterations of any single loop. Strict runs fail with
+    /// [`GsError::LimitExceeded`] when it is reached.
+    pub max_loops: u64,
+    /// Bounds on the resources a program may use, in either mode.
+    pub limits: Limits,
+}
+
+impl Config {
+    /// Fail on errors and never cut loops short.
+    pub fn strict() -> Config {
+        Config {
+            lenient: false,
+            max_loops: u64::MAX,
+            limits: Limits::none(),
+        }
+    }
+
+    /// Recover from errors, stop each loop after 2000 iterations, and stop
+    /// runaway recursion before it exhausts memory.
+    pub fn lenient() -> Config {
+        Config {
+            lenient: true,
+            max_loops: 2000,
+            limits: Limits {
+                depth: Some(100_000),
+                ..Limits::none()
+            },
+        }
+    }
+}
+impl Default for Config {
+    fn default() -> Config {
+        Config::strict()
+    }
+}
+
+/// Bounds on what a program may use, for hosting untrusted code. `None`
+/// leaves a resource unbounded.
+///
+/// A program that runs into a limit stops with
+/// [`ErrorKind::LimitExceeded`] naming the [`Limit`], even in lenient mode.
+/// Steps and time are counted afresh by each call to [`Gs::run`] and
+/// [`Gs::output_stack`]; the others bound the interpreter's state whenever
+/// it is checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Tokens executed and loop iterations started, across all nested code.
+    pub steps: Option<u64>,
+    /// Wall-clock time.
+    pub time: Option<Duration>,
+    /// Approximate bytes held by the stack and variables. Operators that
+    /// build large values, like `,` and `*`, check before allocating.
+    pub heap: Option<usize>,
+    /// Blocks, strings and looping builtins running inside each other.
+    pub depth: Option<usize>,
+    /// Values on the stack.
+    pub stack: Option<usize>,
+    /// Bytes printed, in total.
+    pub output: Option<usize>,
+}
+
+impl Limits {
+    /// No limits at all.
+    pub const fn none() -> Limits {
+        Limits {
+            steps: None,
+            time: None,
+            heap: None,
+            depth: None,
+            stack: None,
+            output: None,
+        }
+    }
+}
+
+/// A token about to run, as a debugger shows it.
+#[derive(Clone, Copy, Debug)]
+pub struct Location<'a> {
+    /// The token as written, quotes and all for a string, but only the
+    /// source between the braces for a block; `:` for assignments.
+    pub lexeme: &'a [u8],
+    /// Where the token is in the source of the last [`Gs::run`] or
+    /// [`Gs::load`], or `None` for code that isn't part of it, such as a
+    /// string run with `~`.
+    pub span: Option<Span>,
+    /// The variable an assignment assigns.
+    pub assigns: Option<&'a [u8]>,
+    /// How many blocks or strings the token is running inside.
+    pub depth: usize,
+    /// The span of the block literal the token is in, if it's in one of the
+    /// source.
+    pub block: Option<Span>,
+    pub kind: TokenKind,
+    /// Whether the token is a name rather than a literal. A literal whose
+    /// lexeme was assigned runs the variable too, so `kind` can't tell.
+    pub name: bool,
+}
+
+/// What a token does when it runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Pushes a number, string or block.
+    Literal,
+    /// Runs a builtin.
+    Builtin,
+    /// Runs the value of a variable, which may shadow a builtin or literal.
+    Var,
+    /// Assigns a variable.
+    Assign,
+    /// Nothing: a name without a value, such as whitespace.
+    Nothing,
+}
+
+/// Watches a run token by token, for tracing, profiling and the like; see
+/// [`Gs::run_with`]. Every method does nothing unless overridden.
+pub trait Hook {
+    /// `token` is about to run.
+    fn before(&mut self, _gs: &Gs, _token: &Location) {}
+
+    /// `token` has run, except for any code it called, such as the block run
+    /// by `~`, which runs next.
+    fn after(&mut self, _gs: &Gs, _token: &Location) {}
+
+    /// A program, block or string started running at `depth`. `block` is
+    /// the span of the block literal if it is one of the source.
+    fn enter(&mut self, _gs: &Gs, _block: Option<Span>, _depth: usize) {}
+
+    /// The code running at `depth` returned.
+    fn exit(&mut self, _gs: &Gs, _depth: usize) {}
+}
+
+/// Everything about a run that stepping changes, for going back to it.
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    stack: Vec<Gval>,
+    vars: Vec<Option<Gval>>,
+    lb: Vec<usize>,
+    rng_state: u64,
+    stable: bool,
+    frames: Vec<Frame>,
+    steps: u64,
+    printed: usize,
+    output_len: usize,
+}
+
+impl Snapshot {
+    /// Roughly how many bytes keeping the snapshot costs. Payloads shared
+    /// with the running interpreter are counted as if they weren't.
+    pub fn size(&self) -> usize {
+        let values = self.stack.iter().chain(self.vars.iter().flatten());
+        let items = self.frames.iter().map(|f| match f {
+            Frame::Loop(l) => l.len(),
+            Frame::Code { .. } => 0,
+        });
+        values.map(Gval::shallow_size).sum::<usize>()
+            + size_of::<Gval>() * (self.vars.len() + items.sum::<usize>())
+            + size_of::<Frame>() * self.frames.len()
+            + size_of::<usize>() * self.lb.len()
+    }
+}
+
+/// The state `rand` starts from.
+const RNG_SEED: u64 = 123456789;
+
+/// A GolfScript interpreter.
+///
+/// The stack, variables and output persist across calls to [`Gs::run`], so a
+/// program can be fed in pieces, and values can be pushed or read back
+/// between pieces.
+pub struct Gs {
+    stack: Vec<Gval>,
+    /// Variables, indexed by the id of their name in `symbols`.
+    vars: Vec<Option<Gval>>,
+    symbols: Symbols,
+    lb: Vec<usize>,
+    rng_state: u64,
+    stable: bool,
+    sink: Sink,
+    /// Input streamed to `gets` and `read`, if the host set one.
+    input: Option<Box<dyn BufRead>>,
+    config: Config,
+    /// How many times `run` has been called; identifies the source that
+    /// compiled code's spans refer to.
+    runs: u64,
+    /// The code and loops in progress, innermost last.
+    frames: Vec<Frame>,
+    /// Steps taken by the current top-level call, for [`Limits::steps`].
+    steps: u64,
+    /// Calls to `poll` so far; the clock is read every so often.
+    polls: u64,
+    deadline: Option<Instant>,
+    /// The heap size last measured, and the step to measure it again at.
+    heap: usize,
+    next_measure: u64,
+    /// Bytes printed over the interpreter's lifetime.
+    printed: usize,
+    cancel: CancelToken,
+}
+
+/// One level of execution. Blocks run by pushing a frame rather than by
+/// recursing, so nesting depth is bounded by memory, not the native stack.
+#[derive(Clone, Debug)]
+enum Frame {
+    /// Instructions of a program or block; `pc` is the next one to run.
+    Code {
+        code: Rc<Code>,
+        pc: usize,
+        located: bool,
+    },
+    /// A builtin that runs code repeatedly, waiting for the code it called
+    /// last to return.
+    Loop(Loop),
+}
+
+/// The state of a looping builtin between calls.
+#[derive(Clone, Debug)]
+enum Loop {
+    /// `*` with an int: run `block` `n` more times.
+    Times { block: Block, n: Int, loops: u64 },
+    /// `/` and `*` folds: push each item and run `block` on it.
+    Each {
+        block: Block,
+        items: std::vec::IntoIter<Gval>,
+    },
+    /// `%`: collect whatever each call leaves above `lb`.
+    Map {
+        block: Block,
+        items: std::vec::IntoIter<Gval>,
+        lb: Option<usize>,
+        out: Vec<Gval>,
+        shape: Shape,
+    },
+    /// `,` with a block: keep the items `block` accepts.
+    Select {
+        block: Block,
+        items: std::vec::IntoIter<Gval>,
+        current: Option<Gval>,
+        out: Vec<Gval>,
+        shape: Shape,
+    },
+    /// `?` with a block: push the first item `block` accepts.
+    Find {
+        block: Block,
+        items: std::vec::IntoIter<Gval>,
+        current: Option<Gval>,
+    },
+    /// `$` with a block: sort the items by what `block` maps them to.
+    SortBy {
+        block: Block,
+        items: std::vec::IntoIter<Gval>,
+        current: Option<Gval>,
+        keyed: Vec<(Gval, Gval)>,
+        shape: Shape,
+    },
+    /// `do`: run `body` until it leaves a falsey value.
+    Do {
+        body: Gval,
+        loops: u64,
+        started: bool,
+    },
+    /// `while` and `until`; `testing` is set while `cond` runs.
+    While {
+        cond: Gval,
+        body: Gval,
+        until: bool,
+        loops: u64,
+        testing: bool,
+    },
+    /// `/` with two blocks; `testing` is set while `cond` runs.
+    Unfold {
+        cond: Block,
+        step: Block,
+        out: Vec<Gval>,
+        loops: u64,
+        testing: bool,
+    },
+}
+
+impl Loop {
+    /// How many values the loop holds on to.
+    fn len(&self) -> usize {
+        match self {
+            Loop::Times { .. } | Loop::Do { .. } | Loop::While { .. } => 1,
+            Loop::Each { items, .. } | Loop::Find { items, .. } => items.len(),
+            Loop::Map { items, out, .. } | Loop::Select { items, out, .. } => {
+                items.len() + out.len()
+            }
+            Loop::SortBy { items, keyed, .. } => items.len() + 2 * keyed.len(),
+            Loop::Unfold { out, .. } => out.len(),
+        }
+    }
+}
+
+/// What to build from the items a loop keeps.
+#[derive(Clone, Copy, Debug)]
+enum Shape {
+    Arr,
+    Str,
+    Blk,
+}
+
+impl Shape {
+    /// The shape of `val` and its items; ints count as one-item arrays.
+    fn of(val: Gval) -> (Shape, Vec<Gval>) {
+        match val {
+            Gval::Str(a) => (Shape::Str, a.iter().map(|&b| b.into()).collect()),
+            Gval::Blk(a) => (Shape::Blk, a.source().iter().map(|&b| b.into()).collect()),
+            Gval::Arr(a) => (Shape::Arr, Rc::unwrap_or_clone(a)),
+            n @ Gval::Int(_) => (Shape::Arr, vec![n]),
+        }
+    }
+
+    fn build(self, vs: Vec<Gval>) -> Gval {
+        match self {
+            Shape::Arr => Gval::Arr(vs.into()),
+            Shape::Str => Gval::Str(flatten(vs).into()),
+            Shape::Blk => Gval::Blk(flatten(vs).into()),
+        }
+    }
+}
+
+impl Default for Gs {
+    fn default() -> Gs {
+        Gs::new()
+    }
+}
+
+impl Gs {
+    /// An interpreter with an empty stack and the strict configuration.
+    pub fn new() -> Gs {
+        Gs::with_config(Config::strict())
+    }
+
+    pub fn with_config(config: Config) -> Gs {
+        Gs {
+            stack: vec![],
+            vars: vec![],
+            symbols: Symbols::new(),
+            lb: vec![],
+            rng_state: RNG_SEED,
+            stable: true,
+            sink: Sink::buffer(),
+            input: None,
+            config,
+            runs: 0,
+            frames: vec![],
+            steps: 0,
+            polls: 0,
+            deadline: None,
+            heap: 0,
+            next_measure: 0,
+            printed: 0,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// False once a lenient run had to paper over an error.
+    pub fn stable(&self) -> bool {
+        self.stable
+    }
+
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// A token that stops this interpreter's current or next run when
+    /// cancelled, from any thread.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// The stack, bottom first.
+    pub fn stack(&self) -> &[Gval] {
+        &self.stack
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Vec<Gval> {
+        &mut self.stack
+    }
+
+    /// Removes and returns every value on the stack, bottom first.
+    pub fn take_stack(&mut self) -> Vec<Gval> {
+        self.lb.clear();
+        std::mem::take(&mut self.stack)
+    }
+
+    pub fn push(&mut self, val: Gval) {
+        self.stack.push(val)
+    }
+
+    /// The value assigned to `name` with `:`, if any.
+    pub fn var(&self, name: &[u8]) -> Option<&Gval> {
+        self.vars.get(self.symbols.get(name)?)?.as_ref()
+    }
+
+    /// Assigns `name` as if by `:name`. Assigning a block makes `name` run it.
+    pub fn set_var(&mut self, name: &[u8], val: Gval) {
+        let symbol = self.symbols.intern(name);
+        self.assign(symbol, val);
+    }
+
+    /// Every assigned variable, in the order their names were first seen.
+    pub fn vars(&self) -> impl Iterator<Item = (&[u8], &Gval)> {
+        self.vars
+            .iter()
+            .enumerate()
+            .filter_map(|(id, v)| Some((self.symbols.name(id), v.as_ref()?)))
+    }
+
+    /// Restarts `rand` from its initial state, so it repeats the numbers it
+    /// gave from the start.
+    pub fn reset_rng(&mut self) {
+        self.rng_state = RNG_SEED;
+    }
+
+    fn assign(&mut self, symbol: Symbol, val: Gval) {
+        if symbol >= self.vars.len() {
+            self.vars.resize(symbol + 1, None);
+        }
+        self.vars[symbol] = Some(val);
+    }
+
+    /// Everything printed so far, if the sink collects output in memory.
+    pub fn output(&self) -> &[u8] {
+        self.sink.bytes()
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.sink.take()
+    }
+
+    pub fn sink(&self) -> &Sink {
+        &self.sink
+    }
+
+    /// Sends output to `sink` from now on, returning the previous sink.
+    pub fn set_sink(&mut self, sink: Sink) -> Sink {
+        std::mem::replace(&mut self.sink, sink)
+    }
+
+    /// Streams `input` to the program through the `gets` and `read`
+    /// builtins, for input too large or too interactive to push up front.
+    ///
+    /// Without an input stream those names do nothing, as in standard
+    /// GolfScript, where the whole input is a string on the stack.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// Flushes a sink that writes output through.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.sink.flush()
+    }
+
+    /// Prints the whole stack and empties it, as GolfScript does when a
+    /// program ends.
+    pub fn output_stack(&mut self) -> Result<(), GsError> {
+        let stack = self.take_stack();
+        self.push(Gval::Arr(stack.into()));
+        self.run_nested(b"puts")
+    }
+
+    fn print(&mut self, bytes: &[u8]) -> Result<(), GsError> {
+        let printed = self.printed + bytes.len();
+        if self.config.limits.output.is_some_and(|max| printed > max) {
+            return Err(GsError::limit(Limit::Output));
+        }
+        self.printed = printed;
+        self.sink.write(bytes).map_err(GsError::output)
+    }
+
+    /// Fails with `err` in strict mode; in lenient mode records that the run
+    /// is unstable and returns `Ok` so the caller can fall back.
+    fn recover(&mut self, err: GsError) -> Result<(), GsError> {
+        if !self.config.lenient {
+            return Err(err);
+        }
+        self.stable = false;
+        Ok(())
+    }
+
+    /// Counts one iteration of a loop; false once `max_loops` is reached.
+    fn tick(&mut self, loops: &mut u64) -> Result<bool, GsError> {
+        self.poll()?;
+        if *loops >= self.config.max_loops {
+            self.recover(GsError::limit(Limit::Loop))?;
+            return Ok(false);
+        }
+        *loops += 1;
+        Ok(true)
+    }
+
+    /// Checks for cancellation and the limits that can't wait for the next
+    /// step, reading the clock only every so often.
+    fn poll(&mut self) -> Result<(), GsError> {
+        if self.cancel.take() {
+            return Err(ErrorKind::Cancelled { op: String::new() }.into());
+        }
+        self.polls = self.polls.wrapping_add(1);
+        if self.polls.is_multiple_of(1024) && self.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(GsError::limit(Limit::Time));
+        }
+        Ok(())
+    }
+
+    /// Counts a step and checks it against [`Limits`].
+    fn count_step(&mut self) -> Result<(), GsError> {
+        self.steps += 1;
+        let limits = self.config.limits;
+        if limits.steps.is_some_and(|max| self.steps > max) {
+            return Err(GsError::limit(Limit::Steps));
+        }
+        if let Some(max) = limits.heap {
+            if self.steps >= self.next_measure {
+                self.heap = self.heap_size();
+                if self.heap > max {
+                    return Err(GsError::limit(Limit::Heap));
+                }
+                // measuring takes time in proportion to the heap, so do it
+                // less often as the heap grows
+                self.next_measure = self.steps + (self.heap as u64 / 64).max(1024);
+            }
+        }
+        self.poll()
+    }
+
+    /// Roughly how many bytes the stack and variables hold.
+    fn heap_size(&self) -> usize {
+        let vars = self.vars.iter().flatten();
+        self.stack.iter().chain(vars).map(Gval::heap_size).sum()
+    }
+
+    /// Fails if building a value of about `bytes` would go over
+    /// [`Limits::heap`].
+    fn reserve(&self, bytes: usize) -> Result<(), GsError> {
+        match self.config.limits.heap {
+            Some(max) if self.heap.saturating_add(bytes) > max => Err(GsError::limit(Limit::Heap)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts counting steps and time for a top-level call.
+    fn begin(&mut self) {
+        if self.frames.is_empty() {
+            self.steps = 0;
+            self.next_measure = 0;
+            self.deadline = self.config.limits.time.map(|t| Instant::now() + t);
+        }
+    }
+
+    /// Runs `code` against the current stack and variables. Error spans are
+    /// relative to `code`.
+    pub fn run(&mut self, code: &[u8]) -> Result<(), GsError> {
+        let base = self.frames.len();
+        self.enter_source(code)?;
+        self.finish(base, None)
+    }
+
+    /// [`Gs::run`], reporting every token and call to `hook`.
+    pub fn run_with(&mut self, code: &[u8], hook: &mut dyn Hook) -> Result<(), GsError> {
+        let base = self.frames.len();
+        self.enter_source(code)?;
+        if self.frames.len() > base {
+            hook.enter(self, None, self.code_depth() - 1);
+        }
+        self.finish(base, Some(hook))
+    }
+
+    /// Prepares to run `code` a token at a time with [`Gs::step_token`],
+    /// instead of all at once. Error spans are relative to `code`.
+    pub fn load(&mut self, code: &[u8]) -> Result<(), GsError> {
+        self.enter_source(code)?;
+        self.settle()
+    }
+
+    /// Runs the next token, descending into any code it calls, and stops at
+    /// the token after it. Returns whether anything is left to run.
+    ///
+    /// An error abandons everything that was loaded.
+    pub fn step_token(&mut self) -> Result<bool, GsError> {
+        if self.frames.is_empty() {
+            return Ok(false);
+        }
+        if let Err(e) = self.step() {
+            return Err(self.unwind(e, 0));
+        }
+        self.settle()?;
+        Ok(!self.frames.is_empty())
+    }
+
+    /// The token [`Gs::step_token`] will run next.
+    pub fn next_token(&self) -> Option<Location<'_>> {
+        let Some(Frame::Code { code, pc, located }) = self.frames.last() else {
+            return None;
+        };
+        self.locate(code, *pc, *located, self.code_depth() - 1)
+    }
+
+    fn locate<'a>(
+        &'a self,
+        code: &'a Code,
+        pc: usize,
+        located: bool,
+        depth: usize,
+    ) -> Option<Location<'a>> {
+        let instr = code.instrs.get(pc)?;
+        let assigns = match instr.op {
+            Op::Assign(Some(name)) => Some(self.symbols.name(name)),
+            _ => None,
+        };
+        let kind = match instr.op {
+            Op::Assign(_) => TokenKind::Assign,
+            _ if matches!(self.vars.get(instr.symbol), Some(Some(_))) => TokenKind::Var,
+            Op::Builtin(_) => TokenKind::Builtin,
+            Op::Push(_) | Op::Block(_) => TokenKind::Literal,
+            Op::Symbol => TokenKind::Nothing,
+        };
+        Some(Location {
+            lexeme: &instr.lexeme,
+            span: located.then_some(instr.span),
+            assigns,
+            depth,
+            block: code.span.filter(|_| located),
+            kind,
+            name: matches!(instr.op, Op::Builtin(_) | Op::Symbol),
+        })
+    }
+
+    /// How many programs, blocks and strings are running inside each other.
+    fn code_depth(&self) -> usize {
+        let code = self
+            .frames
+            .iter()
+            .filter(|f| matches!(f, Frame::Code { .. }));
+        code.count()
+    }
+
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            stack: self.stack.clone(),
+            vars: self.vars.clone(),
+            lb: self.lb.clone(),
+            rng_state: self.rng_state,
+            stable: self.stable,
+            frames: self.frames.clone(),
+            steps: self.steps,
+            printed: self.printed,
+            output_len: self.sink.bytes().len(),
+        }
+    }
+
+    /// Goes back to `snapshot`. Output the sink has already written
+    /// elsewhere stays written.
+    pub(crate) fn restore(&mut self, snapshot: Snapshot) {
+        self.stack = snapshot.stack;
+        self.vars = snapshot.vars;
+        self.lb = snapshot.lb;
+        self.rng_state = snapshot.rng_state;
+        self.stable = snapshot.stable;
+        self.frames = snapshot.frames;
+        self.steps = snapshot.steps;
+        self.printed = snapshot.printed;
+        self.sink.truncate(snapshot.output_len);
+    }
+
+    /// Compiles source given to [`Gs::run`] or [`Gs::load`] and starts
+    /// running it.
+    fn enter_source(&mut self, code: &[u8]) -> Result<(), GsError> {
+        self.runs += 1;
+        self.begin();
+        match compile(code, Some(self.runs), &mut self.symbols) {
+            Ok(compiled) => self.enter(Rc::new(compiled))?,
+            Err(e) => self.compile_error(e, Some(code))?,
+        }
+        Ok(())
+    }
+
+    /// Steps until the innermost frame is about to run a token, or nothing
+    /// is left to run.
+    fn settle(&mut self) -> Result<(), GsError> {
+        while !self.frames.is_empty() && self.next_token().is_none() {
+            if let Err(e) = self.step() {
+                return Err(self.unwind(e, 0));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs source that isn't part of the source given to [`Gs::run`] to
+    /// completion.
+    fn run_nested(&mut self, code: &[u8]) -> Result<(), GsError> {
+        self.begin();
+        let base = self.frames.len();
+        self.eval(code)?;
+        self.finish(base, None)
+    }
+
+    /// Starts running source that isn't part of the source given to
+    /// [`Gs::run`], such as a string evaluated with `~`.
+    fn eval(&mut self, code: &[u8]) -> Result<(), GsError> {
+        match compile(code, None, &mut self.symbols) {
+            Ok(compiled) => self.enter(Rc::new(compiled))?,
+            Err(e) => self.compile_error(e, None)?,
+        }
+        Ok(())
+    }
+
+    /// Starts running `block`.
+    fn call(&mut self, block: &Block) -> Result<(), GsError> {
+        match block.code(&mut self.symbols) {
+            Ok(code) => self.enter(code)?,
+            Err(e) => self.compile_error(e, None)?,
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, code: Rc<Code>) -> Result<(), GsError> {
+        if self
+            .config
+            .limits
+            .depth
+            .is_some_and(|max| self.frames.len() >= max)
+        {
+            return Err(GsError::limit(Limit::Depth));
+        }
+        let located = code.origin == Some(self.runs);
+        self.frames.push(Frame::Code {
+            code,
+            pc: 0,
+            located,
+        });
+        Ok(())
+    }
+
+    /// Reports a parse error, located if `src` is the source of the current
+    /// [`Gs::run`].
+    fn compile_error(&mut self, e: CompileError, src: Option<&[u8]>) -> Result<(), GsError> {
+        let mut err = GsError::new(ErrorKind::Parse {
+            message: e.message.to_owned(),
+        });
+        err.span = src.map(|src| Span::point(src, e.offset));
+        self.recover(err)
+    }
+
+    /// Runs the frames above `base` until they have all returned.
+    fn finish(&mut self, base: usize, mut hook: Option<&mut dyn Hook>) -> Result<(), GsError> {
+        while self.frames.len() > base {
+            let stepped = match hook.as_deref_mut() {
+                Some(hook) => self.step_hooked(hook),
+                None => self.step(),
+            };
+            if let Err(e) = stepped {
+                return Err(self.unwind(e, base));
+            }
+        }
+        Ok(())
+    }
+
+    /// [`Gs::step`], reporting what it did to `hook`.
+    fn step_hooked(&mut self, hook: &mut dyn Hook) -> Result<(), GsError> {
+        let frames = self.frames.len();
+        let depth = self.code_depth().saturating_sub(1);
+        let token = match self.frames.last() {
+            Some(Frame::Code { code, pc, located }) => Some((code.clone(), *pc, *located)),
+            _ => None,
+        };
+        if let Some((code, pc, located)) = &token {
+            if let Some(loc) = self.locate(code, *pc, *located, depth) {
+                hook.before(self, &loc);
+            }
+        }
+        self.step()?;
+        if let Some((code, pc, located)) = &token {
+            match self.locate(code, *pc, *located, depth) {
+                Some(loc) => hook.after(self, &loc),
+                None => hook.exit(self, depth),
+            }
+        }
+        if self.frames.len() > frames {
+            if let Some(Frame::Code { code, located, .. }) = self.frames.last() {
+                let block = code.span.filter(|_| *located);
+                hook.enter(self, block, self.code_depth() - 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the next instruction of the innermost frame, or moves its loop on
+    /// to the next call.
+    fn step(&mut self) -> Result<(), GsError> {
+        self.count_step()?;
+        self.step_frame()?;
+        if self
+            .config
+            .limits
+            .stack
+            .is_some_and(|max| self.stack.len() > max)
+        {
+            return Err(GsError::limit(Limit::Stack));
+        }
+        Ok(())
+    }
+
+    fn step_frame(&mut self) -> Result<(), GsError> {
+        match self.frames.last_mut() {
+            Some(Frame::Code { code, pc, .. }) => {
+                if *pc == code.instrs.len() {
+                    self.frames.pop();
+                    return Ok(());
+                }
+                let code = code.clone();
+                let instr = &code.instrs[*pc];
+                *pc += 1;
+                self.run_instr(instr)
+            }
+            Some(Frame::Loop(_)) => {
+                let Some(Frame::Loop(mut l)) = self.frames.pop() else {
+                    unreachable!()
+                };
+                if let Some(next) = self.advance(&mut l)? {
+                    self.frames.push(Frame::Loop(l));
+                    self.go(next)?;
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the frames above `base`, attributing `e` to the innermost token
+    /// that was running and to the innermost span that refers to the source
+    /// of [`Gs::run`].
+    fn unwind(&mut self, mut e: GsError, base: usize) -> GsError {
+        for frame in self.frames.drain(base..).rev() {
+            if let Frame::Code { code, pc, located } = frame {
+                if let Some(instr) = pc.checked_sub(1).map(|i| &code.instrs[i]) {
+                    e = e.at(&instr.lexeme, located.then_some(instr.span));
+                }
+            }
+        }
+        e
+    }
+
+    /// Takes the result of the last call of `l` off the stack and returns the
+    /// next value to run, or pushes the result of the loop and returns `None`.
+    fn advance(&mut self, l: &mut Loop) -> Result<Option<Gval>, GsError> {
+        match l {
+            Loop::Times { block, n, loops } => {
+                if n.is_positive() && self.tick(loops)? {
+                    *n -= 1;
+                    return Ok(Some(Gval::Blk(block.clone())));
+                }
+            }
+            Loop::Each { block, items } => {
+                if let Some(v) = items.next() {
+                    self.push(v);
+                    return Ok(Some(Gval::Blk(block.clone())));
+                }
+            }
+            Loop::Map {
+                block,
+                items,
+                lb,
+                out,
+                shape,
+            } => {
+                if let Some(lb) = lb.take() {
+                    out.extend(self.stack.drain(lb.min(self.stack.len())..));
+                }
+                match items.next() {
+                    Some(v) => {
+                        *lb = Some(self.stack.len());
+                        self.push(v);
+                        return Ok(Some(Gval::Blk(block.clone())));
+                    }
+                    None => self.push(shape.build(std::mem::take(out))),
+                }
+            }
+            Loop::Select {
+                block,
+                items,
+                current,
+                out,
+                shape,
+            } => {
+                if let Some(v) = current.take() {
+                    if self.pop()?.truthy() {
+                        out.push(v);
+                    }
+                }
+                match items.next() {
+                    Some(v) => {
+                        self.push(v.clone());
+                        *current = Some(v);
+                        return Ok(Some(Gval::Blk(block.clone())));
+                    }
+                    None => self.push(shape.build(std::mem::take(out))),
+                }
+            }
+            Loop::Find {
+                block,
+                items,
+                current,
+            } => {
+                if let Some(v) = current.take() {
+                    if self.pop()?.truthy() {
+                        self.push(v);
+                        return Ok(None);
+                    }
+                }
+                if let Some(v) = items.next() {
+                    self.push(v.clone());
+                    *current = Some(v);
+                    return Ok(Some(Gval::Blk(block.clone())));
+                }
+            }
+            Loop::SortBy {
+                block,
+                items,
+                current,
+                keyed,
+                shape,
+            } => {
+                if let Some(v) = current.take() {
+                    keyed.push((self.pop()?, v));
+                }
+                match items.next() {
+                    Some(v) => {
+                        self.push(v.clone());
+                        *current = Some(v);
+                        return Ok(Some(Gval::Blk(block.clone())));
+                    }
+                    None => {
+                        let mut keyed = std::mem::take(keyed);
+                        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+                        self.push(shape.build(keyed.into_iter().map(|x| x.1).collect()));
+                    }
+                }
+            }
+            Loop::Do {
+                body,
+                loops,
+                started,
+            } => {
+                if *started && self.pop()?.falsey() {
+                    return Ok(None);
+                }
+                if self.tick(loops)? {
+                    *started = true;
+                    return Ok(Some(body.clone()));
+                }
+            }
+            Loop::While {
+                cond,
+                body,
+                until,
+                loops,
+                testing,
+            } => {
+                if *testing {
+                    *testing = false;
+                    if self.pop()?.falsey() != *until {
+                        return Ok(None);
+                    }
+                    return Ok(Some(body.clone()));
+                }
+                if self.tick(loops)? {
+                    *testing = true;
+                    return Ok(Some(cond.clone()));
+                }
+            }
+            Loop::Unfold {
+                cond,
+                step,
+                out,
+                loops,
+                testing,
+            } => {
+                if *testing {
+                    *testing = false;
+                    if self.pop()?.truthy() {
+                        out.push(self.top()?);
+                        return Ok(Some(Gval::Blk(step.clone())));
+                    }
+                } else if self.tick(loops)? {
+                    let t = self.top()?;
+                    self.push(t);
+                    *testing = true;
+                    return Ok(Some(Gval::Blk(cond.clone())));
+                }
+                self.pop()?;
+                self.push(Gval::Arr(std::mem::take(out).into()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn top(&mut self) -> Result<Gval, GsError> {
+        match self.stack.last() {
+            Some(a) => Ok(a.clone()),
+            None => {
+                self.recover(GsError::underflow())?;
+                Ok(Gval::Arr(vec![].into()))
+            }
+        }
+    }
+
+    fn dup(&mut self) -> Result<(), GsError> {
+        let a = self.pop()?;
+        self.push(a.clone());
+        self.push(a);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Gval, GsError> {
+        let mut i = self.lb.len();
+        while i > 0 && self.lb[i - 1] >= self.stack.len() {
+            i -= 1;
+            if self.lb[i] > 0 {
+                self.lb[i] -= 1;
+            }
+        }
+        match self.stack.pop() {
+            Some(a) => Ok(a),
+            None => {
+                self.recover(GsError::underflow())?;
+                Ok(Gval::Arr(vec![].into()))
+            }
+        }
+    }
+
+    /// Reports `op` applied to operands of the wrong types.
+    fn mismatch(&mut self, op: &str, operands: &[&Gval]) -> Result<(), GsError> {
+        let types: Vec<&'static str> = operands.iter().map(|v| v.type_name()).collect();
+        self.recover(GsError::type_mismatch(op, &types))
+    }
+
+    fn tilde(&mut self) -> Result<(), GsError> {
+        match self.pop()? {
+            Gval::Int(n) => self.push(Gval::Int(!n)),
+            Gval::Arr(vs) => self.stack.extend(Rc::unwrap_or_clone(vs)),
+            Gval::Str(bs) => self.eval(&bs)?,
+            Gval::Blk(b) => self.call(&b)?,
+        }
+        Ok(())
+    }
+
+    fn backtick(&mut self) -> Result<(), GsError> {
+        let bs = self.pop()?.inspect();
+        self.push(Gval::Str(bs.into()));
+        Ok(())
+    }
+
+    fn bang(&mut self) -> Result<(), GsError> {
+        let f = self.pop()?.falsey();
+        self.push(Gval::bool(f));
+        Ok(())
+    }
+
+    fn at_sign(&mut self) -> Result<(), GsError> {
+        let c = self.pop()?;
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(b);
+        self.push(c);
+        self.push(a);
+        Ok(())
+    }
+
+    fn dollar(&mut self) -> Result<(), GsError> {
+        match self.pop()? {
+            Gval::Int(n) => {
+                let len: Int = self.stack.len().into();
+                if n < Int::from(-1) {
+                    if let Some(i) = (-n - 2).to_usize() {
+                        if i < self.stack.len() {
+                            self.push(self.stack[i].clone());
+                        }
+                    }
+                } else if !n.is_negative() && n < len {
+                    if let Some(i) = (len - 1 - n).to_usize() {
+                        self.push(self.stack[i].clone());
+                    }
+                }
+            }
+            Gval::Arr(mut vs) => {
+                Rc::make_mut(&mut vs).sort();
+                self.push(Gval::Arr(vs));
+            }
+            Gval::Str(mut bs) => {
+                Rc::make_mut(&mut bs).sort();
+                self.push(Gval::Str(bs));
+            }
+            Gval::Blk(code) => match self.pop()? {
+                n @ Gval::Int(_) => {
+                    self.mismatch("$", &[&n, &Gval::Blk(code)])?;
+                    self.push(n);
+                }
+                vs => {
+                    let (shape, items) = Shape::of(vs);
+                    self.start(Loop::SortBy {
+                        block: code,
+                        items: items.into_iter(),
+                        current: None,
+                        keyed: vec![],
+                        shape,
+                    });
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Starts a looping builtin; it makes its first call on the next step.
+    fn start(&mut self, l: Loop) {
+        self.frames.push(Frame::Loop(l));
+    }
+
+    fn plus(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.reserve(a.shallow_size() + b.shallow_size())?;
+        self.push(a.plus(b));
+        Ok(())
+    }
+
+    fn minus(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        match coerce(a, b) {
+            Coerced::Ints(x, y) => self.push(Gval::Int(x - y)),
+            Coerced::Arrs(x, y) => self.push(Gval::Arr(set_subtract(&x, &y).into())),
+            Coerced::Strs(x, y) => self.push(Gval::Str(set_subtract(&x, &y).into())),
+            Coerced::Blks(x, y) => self.push(Gval::Blk(set_subtract(&x, &y).into())),
+        }
+        Ok(())
+    }
+
+    fn asterisk(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        use Gval::*;
+        match (a, b) {
+            // multiply
+            (Int(a), Int(b)) => self.push(Int(a * b)),
+            // join
+            (Arr(a), Arr(sep)) => self.push(join(&a, Arr(sep))),
+            (Arr(a), Str(sep)) | (Str(sep), Arr(a)) => self.push(join(&a, Str(sep))),
+            (Str(a), Str(sep)) => {
+                let a: Vec<Gval> = a.iter().map(|&x| Gval::from(vec![x])).collect();
+                self.push(join(&a, Str(sep)));
+            }
+
+            // fold
+            (Blk(code), a @ (Arr(_) | Str(_) | Blk(_))) | (a @ (Arr(_) | Str(_)), Blk(code)) => {
+                let mut items = Shape::of(a).1.into_iter();
+                if let Some(first) = items.next() {
+                    self.push(first);
+                }
+                self.start(Loop::Each { block: code, items });
+            }
+
+            // repeat
+            (Int(n), Arr(a)) | (Arr(a), Int(n)) => {
+                self.reserve(
+                    size_of::<Gval>()
+                        .saturating_mul(a.len())
+                        .saturating_mul(count(&n)),
+                )?;
+                let r = self.repeat(&a, &n)?;
+                self.push(Arr(r.into()))
+            }
+            (Int(n), Str(a)) | (Str(a), Int(n)) => {
+                self.reserve(a.len().saturating_mul(count(&n)))?;
+                let r = self.repeat(&a, &n)?;
+                self.push(Str(r.into()))
+            }
+
+            // times
+            (Int(n), Blk(block)) | (Blk(block), Int(n)) => {
+                self.start(Loop::Times { block, n, loops: 0 })
+            }
+        }
+        Ok(())
+    }
+
+    /// `a` repeated `n` times, or nothing if `n` isn't positive. The result
+    /// is allocated up front, failing if it can't be, and filled a chunk at
+    /// a time so the time limit and cancellation can stop a huge one.
+    fn repeat<T: Clone>(&mut self, a: &[T], n: &Int) -> Result<Vec<T>, GsError> {
+        const CHUNK: usize = 1 << 16;
+        if a.is_empty() || !n.is_positive() {
+            return Ok(vec![]);
+        }
+        let len = n
+            .to_usize()
+            .and_then(|n| n.checked_mul(a.len()))
+            .ok_or_else(|| GsError::limit(Limit::Heap))?;
+        let mut r = Vec::new();
+        r.try_reserve_exact(len)
+            .map_err(|_| GsError::limit(Limit::Heap))?;
+        r.extend_from_slice(a);
+        while r.len() < len {
+            self.poll()?;
+            let more = (len - r.len()).min(r.len()).min(CHUNK);
+            r.extend_from_within(..more);
+        }
+        Ok(r)
+    }
+
+    /// Splits `a` on `sep` into an array of pieces, the shared core of `/`
+    /// and `%`.
+    fn split<T: Clone + Eq>(
+        &mut self,
+        op: &str,
+        a: &[T],
+        sep: &[T],
+        clean: bool,
+    ) -> Result<Gval, GsError>
+    where
+        Gval: From<Vec<T>>,
+    {
+        let pieces = if sep.is_empty() {
+            self.recover(GsError::division_by_zero(op))?;
+            vec![a.to_vec()]
+        } else {
+            split(a, sep, clean)
+        };
+        let pieces: Vec<Gval> = pieces.into_iter().map(Gval::from).collect();
+        Ok(pieces.into())
+    }
+
+    fn slash(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        use Gval::*;
+        match (a, b) {
+            // divide
+            (Int(a), Int(b)) => {
+                if b.is_zero() {
+                    self.recover(GsError::division_by_zero("/"))?;
+                    self.push(Int(0.into()));
+                } else {
+                    self.push(Int(a.div_floor(&b)))
+                }
+            }
+            // split
+            (Arr(a), Arr(sep)) => {
+                let s = self.split("/", &a, &sep, false)?;
+                self.push(s);
+            }
+            (Str(a), Str(sep)) => {
+                let s = self.split("/", &a, &sep, false)?;
+                self.push(s);
+            }
+            (Arr(a), Str(sep)) | (Str(sep), Arr(a)) => {
+                let sep: Vec<Gval> = sep.iter().map(|&x| x.into()).collect();
+                let s = self.split("/", &a, &sep, false)?;
+                self.push(s);
+            }
+
+            // each
+            (a @ (Arr(_) | Str(_)), Blk(code)) | (Blk(code), a @ (Arr(_) | Str(_))) => {
+                self.start(Loop::Each {
+                    block: code,
+                    items: Shape::of(a).1.into_iter(),
+                })
+            }
+
+            // chunk
+            (Int(n), Arr(a)) | (Arr(a), Int(n)) => {
+                if n.is_zero() {
+                    self.recover(GsError::division_by_zero("/"))?;
+                    self.push(Arr(a));
+                } else {
+                    let c: Vec<Gval> = chunk(&a, n).into_iter().map(Gval::from).collect();
+                    self.push(c.into());
+                }
+            }
+            (Int(n), Str(a)) | (Str(a), Int(n)) => {
+                if n.is_zero() {
+                    self.recover(GsError::division_by_zero("/"))?;
+                    self.push(Str(a));
+                } else {
+                    let c: Vec<Gval> = chunk(&a, n).into_iter().map(Gval::from).collect();
+                    self.push(c.into());
+                }
+            }
+
+            // unfold
+            (Blk(cond), Blk(step)) => self.start(Loop::Unfold {
+                cond,
+                step,
+                out: vec![],
+                loops: 0,
+                testing: false,
+            }),
+
+            (Blk(code), n @ Int(_)) | (n @ Int(_), Blk(code)) => {
+                self.mismatch("/", &[&n, &Blk(code.clone())])?;
+                self.start(Loop::Each {
+                    block: code,
+                    items: vec![n].into_iter(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn percent(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        use Gval::*;
+        match (a, b) {
+            // modulo
+            (Int(a), Int(b)) => {
+                if b.is_zero() {
+                    self.recover(GsError::division_by_zero("%"))?;
+                    self.push(Int(0.into()));
+                } else {
+                    self.push(Int(a.mod_floor(&b)))
+                }
+            }
+            // clean split
+            (Arr(a), Arr(sep)) => {
+                let s = self.split("%", &a, &sep, true)?;
+                self.push(s);
+            }
+            (Str(a), Str(sep)) => {
+                let s = self.split("%", &a, &sep, true)?;
+                self.push(s);
+            }
+            (Arr(a), Str(sep)) | (Str(sep), Arr(a)) => {
+                let sep: Vec<Gval> = sep.iter().map(|&x| x.into()).collect();
+                let s = self.split("%", &a, &sep, true)?;
+                self.push(s);
+            }
+
+            // map
+            (a @ (Arr(_) | Str(_)), Blk(code)) | (Blk(code), a @ (Arr(_) | Str(_))) => {
+                self.map(code, a)
+            }
+
+            // every nth
+            (Int(n), Arr(a)) | (Arr(a), Int(n)) => {
+                if n.is_zero() {
+                    self.recover(GsError::division_by_zero("%"))?;
+                    self.push(Arr(a));
+                } else {
+                    self.push(Arr(every_nth(&a, n).into()));
+                }
+            }
+            (Int(n), Str(a)) | (Str(a), Int(n)) => {
+                if n.is_zero() {
+                    self.recover(GsError::division_by_zero("%"))?;
+                    self.push(Str(a));
+                } else {
+                    self.push(Str(every_nth(&a, n).into()));
+                }
+            }
+
+            // unimplemented
+            (n @ Int(_), Blk(code)) | (Blk(code), n @ Int(_)) => {
+                self.mismatch("%", &[&n, &Blk(code.clone())])?;
+                self.map(code, vec![n].into());
+            }
+            (Blk(a), Blk(code)) => {
+                self.mismatch("%", &[&Blk(a.clone()), &Blk(code.clone())])?;
+                self.map(code, vec![Blk(a)].into());
+            }
+        }
+        Ok(())
+    }
+
+    fn map(&mut self, block: Block, a: Gval) {
+        let (shape, items) = Shape::of(a);
+        self.start(Loop::Map {
+            block,
+            items: items.into_iter(),
+            lb: None,
+            out: vec![],
+            shape,
+        });
+    }
+
+    fn vertical_bar(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(match coerce(a, b) {
+            Coerced::Ints(x, y) => Gval::Int(x | y),
+            Coerced::Arrs(x, y) => Gval::Arr(set_or(&x, &y).into()),
+            Coerced::Strs(x, y) => Gval::Str(set_or(&x, &y).into()),
+            Coerced::Blks(x, y) => Gval::Blk(set_or(&x, &y).into()),
+        });
+        Ok(())
+    }
+
+    fn ampersand(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(match coerce(a, b) {
+            Coerced::Ints(x, y) => Gval::Int(x & y),
+            Coerced::Arrs(x, y) => Gval::Arr(set_and(&x, &y).into()),
+            Coerced::Strs(x, y) => Gval::Str(set_and(&x, &y).into()),
+            Coerced::Blks(x, y) => Gval::Blk(set_and(&x, &y).into()),
+        });
+        Ok(())
+    }
+
+    fn caret(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(match coerce(a, b) {
+            Coerced::Ints(x, y) => Gval::Int(x ^ y),
+            Coerced::Arrs(x, y) => Gval::Arr(set_xor(&x, &y).into()),
+            Coerced::Strs(x, y) => Gval::Str(set_xor(&x, &y).into()),
+            Coerced::Blks(x, y) => Gval::Blk(set_xor(&x, &y).into()),
+        });
+        Ok(())
+    }
+
+    fn lteqgt(&mut self, ordering: Ordering) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        use Gval::*;
+        use Ordering::*;
+        match (ordering, a, b) {
+            (Equal, Int(i), Arr(a)) | (Equal, Arr(a), Int(i)) => {
+                if let Some(x) = index(&a, i) {
+                    self.push(x.clone());
+                }
+            }
+            (Equal, Int(i), Str(a)) | (Equal, Str(a), Int(i)) => {
+                if let Some(&x) = index(&a, i) {
+                    self.push(x.into());
+                }
+            }
+            (Equal, Int(i), Blk(a)) | (Equal, Blk(a), Int(i)) => {
+                if let Some(&x) = index(a.source(), i) {
+                    self.push(x.into());
+                }
+            }
+            (o, Int(i), Arr(a)) | (o, Arr(a), Int(i)) => self.push(Arr(slice(o, &a, i).into())),
+            (o, Int(i), Str(a)) | (o, Str(a), Int(i)) => self.push(Str(slice(o, &a, i).into())),
+            (o, Int(i), Blk(a)) | (o, Blk(a), Int(i)) => {
+                self.push(Blk(slice(o, a.source(), i).into()))
+            }
+            (o, x, y) => self.push(Gval::bool(x.cmp(&y) == o)),
+        }
+        Ok(())
+    }
+
+    fn comma(&mut self) -> Result<(), GsError> {
+        use Gval::*;
+        match self.pop()? {
+            Int(n) => {
+                let len = count(&n).min(self.config.max_loops as usize);
+                self.reserve(size_of::<Gval>().saturating_mul(len))?;
+                let mut r: Vec<Gval> = vec![];
+                let mut loops = 0u64;
+                while n > r.len().into() && self.tick(&mut loops)? {
+                    r.push(r.len().into());
+                }
+                self.push(Arr(r.into()));
+            }
+            Arr(a) => self.push(a.len().into()),
+            Str(a) => self.push(a.len().into()),
+            Blk(code) => {
+                let a = self.pop()?;
+                if let Int(_) = a {
+                    self.mismatch(",", &[&a, &Blk(code.clone())])?;
+                }
+                let (shape, items) = Shape::of(a);
+                self.start(Loop::Select {
+                    block: code,
+                    items: items.into_iter(),
+                    current: None,
+                    out: vec![],
+                    shape,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn question(&mut self) -> Result<(), GsError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        use Gval::*;
+        match (a, b) {
+            // power
+            (Int(a), Int(b)) => match b.to_u32() {
+                // lenient runs refuse to build numbers beyond ~10^100
+                Some(e) if self.config.lenient && a.bits() * u64::from(e) > 332 => {
+                    self.recover(GsError::limit(Limit::Power))?;
+                    self.push(Int(a));
+                }
+                Some(e) => {
+                    self.reserve((a.bits().saturating_mul(e.into()) / 8) as usize)?;
+                    self.push(Int(a.pow(e)))
+                }
+                None => self.push(Int(0.into())),
+            },
+
+            // indexof
+            (Arr(h), n @ Int(_))
+            | (n @ Int(_), Arr(h))
+            | (Arr(h), n @ Str(_))
+            | (n @ Str(_), Arr(h))
+            | (Arr(h), n @ Arr(_)) => self.push(
+                h.iter()
+                    .position(|x| *x == n)
+                    .map_or(-1, |i| i as i64)
+                    .into(),
+            ),
+            (Str(h), Int(n)) | (Int(n), Str(h)) => self.push(
+                n.to_u8()
+                    .and_then(|b| h.iter().position(|x| *x == b))
+                    .map_or(-1, |i| i as i64)
+                    .into(),
+            ),
+            (Str(h), Str(n)) => self.push(Gval::Int(string_index(&h, &n))),
+
+            // find
+            (Blk(code), a) | (a, Blk(code)) => {
+                if let Int(_) = a {
+                    self.mismatch("?", &[&a, &Blk(code.clone())])?;
+                }
+                self.start(Loop::Find {
+                    block: code,
+                    items: Shape::of(a).1.into_iter(),
+                    current: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn empty(&mut self, op: &str) -> Result<(), GsError> {
+        self.recover(ErrorKind::EmptyValue { op: op.to_owned() }.into())
+    }
+
+    fn left_paren(&mut self) -> Result<(), GsError> {
+        use Gval::*;
+        match self.pop()? {
+            Int(n) => self.push(Int(n - 1)),
+            Arr(a) if a.is_empty() => self.empty("(")?,
+            Str(a) if a.is_empty() => self.empty("(")?,
+            Blk(a) if a.source().is_empty() => self.empty("(")?,
+            Arr(a) => {
+                self.push(Arr(a[1..].to_vec().into()));
+                self.push(a[0].clone());
+            }
+            Str(a) => {
+                self.push(Str(a[1..].to_vec().into()));
+                self.push(a[0].into());
+            }
+            Blk(a) => {
+                let a = a.source();
+                self.push(Blk(a[1..].to_vec().into()));
+                self.push(a[0].into());
+            }
+        }
+        Ok(())
+    }
+
+    fn right_paren(&mut self) -> Result<(), GsError> {
+        use Gval::*;
+        match self.pop()? {
+            Int(n) => self.push(Int(n + 1)),
+            Arr(mut a) => match Rc::make_mut(&mut a).pop() {
+                Some(l) => {
+                    self.push(Arr(a));
+                    self.push(l);
+                }
+                None => self.empty(")")?,
+            },
+            Str(mut a) => match Rc::make_mut(&mut a).pop() {
+                Some(l) => {
+                    self.push(Str(a));
+                    self.push(l.into());
+                }
+                None => self.empty(")")?,
+            },
+            Blk(a) => {
+                let mut a = a.into_source();
+                match a.pop() {
+                    Some(l) => {
+                        self.push(Blk(a.into()));
+                        self.push(l.into());
+                    }
+                    None => self.empty(")")?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rng(&mut self) -> u64 {
+        let (m, _) = self.rng_state.overflowing_mul(1664525);
+        let (m, _) = m.overflowing_add(1013904223);
+        self.rng_state = m;
+        self.rng_state
+    }
+
+    fn rand(&mut self) -> Result<(), GsError> {
+        let r = match self.pop()? {
+            Gval::Int(n) if n.is_positive() => Int::from(self.rng()).mod_floor(&n),
+            _ => Int::zero(),
+        };
+        self.push(Gval::Int(r));
+        Ok(())
+    }
+
+    fn do_loop(&mut self) -> Result<(), GsError> {
+        let body = self.pop()?;
+        self.start(Loop::Do {
+            body,
+            loops: 0,
+            started: false,
+        });
+        Ok(())
+    }
+
+    fn while_loop(&mut self, until: bool) -> Result<(), GsError> {
+        let body = self.pop()?;
+        let cond = self.pop()?;
+        self.start(Loop::While {
+            cond,
+            body,
+            until,
+            loops: 0,
+            testing: false,
+        });
+        Ok(())
+    }
+
+    fn zip(&mut self) -> Result<(), GsError> {
+        let a = match self.pop()? {
+            Gval::Arr(a) => a,
+            a => {
+                self.mismatch("zip", &[&a])?;
+                self.push(a);
+                return Ok(());
+            }
+        };
+        if let Some(row) = a.iter().find(|row| matches!(row, Gval::Int(_))) {
+            let row = row.clone();
+            self.mismatch("zip", &[&row])?;
+            self.push(Gval::Arr(a));
+            return Ok(());
+        }
+        let mut r = vec![];
+        let blank = a.first().map_or(Gval::Arr(vec![].into()), |x| x.factory());
+        for row in Rc::unwrap_or_clone(a) {
+            self.poll()?;
+            // ints were refused above
+            let Some(row) = row.elements() else { continue };
+            for (y, elem) in row.into_iter().enumerate() {
+                while r.len() < y + 1 {
+                    r.push(blank.clone())
+                }
+                r[y].push(elem)?;
+            }
+        }
+        self.push(Gval::Arr(r.into()));
+        Ok(())
+    }
+
+    fn base(&mut self) -> Result<(), GsError> {
+        let b = match self.pop()? {
+            Gval::Int(b) => b,
+            b => {
+                self.mismatch("base", &[&b])?;
+                self.push(b);
+                return Ok(());
+            }
+        };
+        match self.pop()? {
+            Gval::Int(n) => {
+                if b.is_zero() {
+                    self.recover(GsError::division_by_zero("base"))?;
+                    self.push(Gval::Arr(vec![].into()));
+                    return Ok(());
+                }
+                let mut digits = vec![];
+                let mut i = n.abs();
+                let mut loops = 0u64;
+                while !i.is_zero() && self.tick(&mut loops)? {
+                    let (j, k) = i.div_mod_floor(&b);
+                    i = j;
+                    digits.push(Gval::Int(k));
+                }
+                digits.reverse();
+                self.push(Gval::Arr(digits.into()))
+            }
+            n => {
+                let digits = n.elements().unwrap_or_default();
+                if let Some(digit) = digits.iter().find(|d| !matches!(d, Gval::Int(_))) {
+                    let digit = digit.clone();
+                    self.mismatch("base", &[&digit])?;
+                    self.push(n);
+                    self.push(Gval::Int(b));
+                    return Ok(());
+                }
+                let mut total = Int::zero();
+                for digit in digits {
+                    self.poll()?;
+                    if let Gval::Int(d) = digit {
+                        total = total * b.clone() + d;
+                    }
+                }
+                self.push(Gval::Int(total))
+            }
+        }
+        Ok(())
+    }
+
+    /// `gets`: pushes the next line of input with its newline, or `""` at
+    /// the end.
+    fn gets(&mut self) -> Result<(), GsError> {
+        if self.input.is_none() {
+            return Ok(());
+        }
+        // a prompt printed before the read should be seen before it blocks
+        self.sink.flush().map_err(GsError::output)?;
+        let mut line = vec![];
+        if let Some(input) = &mut self.input {
+            input.read_until(b'\n', &mut line).map_err(GsError::input)?;
+        }
+        self.push(Gval::from(line));
+        Ok(())
+    }
+
+    /// `read`: pushes the next `n` bytes of input, fewer at the end, or all
+    /// that is left if `n` is negative.
+    fn read(&mut self) -> Result<(), GsError> {
+        if self.input.is_none() {
+            return Ok(());
+        }
+        let n = match self.pop()? {
+            Gval::Int(n) => n,
+            a => {
+                self.mismatch("read", &[&a])?;
+                self.push(a);
+                return Ok(());
+            }
+        };
+        self.sink.flush().map_err(GsError::output)?;
+        let mut bytes = vec![];
+        if let Some(input) = &mut self.input {
+            let limit = if n.is_negative() {
+                u64::MAX
+            } else {
+                n.to_u64().unwrap_or(u64::MAX)
+            };
+            input
+                .take(limit)
+                .read_to_end(&mut bytes)
+                .map_err(GsError::input)?;
+        }
+        self.push(Gval::from(bytes));
+        Ok(())
+    }
+
+    fn go(&mut self, val: Gval) -> Result<(), GsError> {
+        match val {
+            Gval::Blk(b) => self.call(&b)?,
+            _ => self.push(val),
+        }
+        Ok(())
+    }
+
+    fn run_instr(&mut self, instr: &Instr) -> Result<(), GsError> {
+        if let Op::Assign(name) = &instr.op {
+            return match (name, self.stack.last()) {
+                (Some(name), Some(t)) => {
+                    self.assign(*name, t.clone());
+                    Ok(())
+                }
+                (Some(_), None) => self.recover(GsError::underflow()),
+                (None, _) => self.recover(
+                    ErrorKind::Parse {
+                        message: "`:` without a name".to_owned(),
+                    }
+                    .into(),
+                ),
+            };
+        }
+        if let Some(Some(v)) = self.vars.get(instr.symbol) {
+            return self.go(v.clone());
+        }
+        match &instr.op {
+            Op::Push(v) => self.push(v.clone()),
+            Op::Block(b) => self.push(Gval::Blk(b.clone())),
+            Op::Builtin(builtin) => self.builtin(*builtin)?,
+            Op::Symbol => {}
+            Op::Assign(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn builtin(&mut self, builtin: Builtin) -> Result<(), GsError> {
+        use Builtin::*;
+        match builtin {
+            Tilde => self.tilde()?,
+            Backtick => self.backtick()?,
+            Bang => self.bang()?,
+            AtSign => self.at_sign()?,
+            Dollar => self.dollar()?,
+            Plus => self.plus()?,
+            Minus => self.minus()?,
+            Asterisk => self.asterisk()?,
+            Slash => self.slash()?,
+            Percent => self.percent()?,
+            VerticalBar => self.vertical_bar()?,
+            Ampersand => self.ampersand()?,
+            Caret => self.caret()?,
+            LeftBracket => self.lb.push(self.stack.len()),
+            RightBracket => {
+                let lb = self.lb.pop().unwrap_or(0).min(self.stack.len());
+                let vs: Vec<Gval> = self.stack.drain(lb..).collect();
+                self.push(Gval::Arr(vs.into()));
+            }
+            Backslash => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(b);
+                self.push(a);
+            }
+            Semicolon => {
+                self.pop()?;
+            }
+            Less => self.lteqgt(Ordering::Less)?,
+            Equal => self.lteqgt(Ordering::Equal)?,
+            Greater => self.lteqgt(Ordering::Greater)?,
+            Comma => self.comma()?,
+            Dot => self.dup()?,
+            Question => self.question()?,
+            LeftParen => self.left_paren()?,
+            RightParen => self.right_paren()?,
+            And => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.go(if a.truthy() { b } else { a })?;
+            }
+            Or => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.go(if a.falsey() { b } else { a })?;
+            }
+            Xor => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(Gval::bool(a.truthy() ^ b.truthy()));
+            }
+            N => self.push(Gval::from(b"\n".to_vec())),
+            Print => {
+                let a = self.pop()?;
+                self.print(&a.to_gs())?;
+            }
+            P => {
+                let a = self.pop()?;
+                self.print(&a.inspect())?;
+                self.print(b"\n")?;
+            }
+            Puts => {
+                let a = self.pop()?;
+                self.print(&a.to_gs())?;
+                self.print(b"\n")?;
+            }
+            Rand => self.rand()?,
+            Do => self.do_loop()?,
+            While => self.while_loop(false)?,
+            Until => self.while_loop(true)?,
+            If => {
+                let c = self.pop()?;
+                let b = self.pop()?;
+                let a = self.pop()?;
+                if a.truthy() {
+                    self.go(b)?;
+                } else {
+                    self.go(c)?;
+                }
+            }
+            Abs => match self.pop()? {
+                Gval::Int(n) => self.push(Gval::Int(n.abs())),
+                a => {
+                    self.mismatch("abs", &[&a])?;
+                    self.push(a);
+                }
+            },
+            Zip => self.zip()?,
+            Base => self.base()?,
+            Gets => self.gets()?,
+            Read => self.read()?,
+        }
+        Ok(())
+    }
+}
+
+/// `n` as a count of repetitions: zero if negative, saturating if huge.
+fn count(n: &Int) -> usize {
+    if n.is_positive() {
+        n.to_usize().unwrap_or(usize::MAX)
+    } else {
+        0
+    }
+}
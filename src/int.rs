@@ -0,0 +1,388 @@
+use num::BigInt;
+use num::Integer;
+use num::Signed;
+use num::ToPrimitive;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, AddAssign, BitAnd, BitOr, BitXor, Mul, Neg, Not, Sub, SubAssign};
+
+/// A GolfScript integer: stored inline while it fits in an `i64`, and
+/// promoted to a [`BigInt`] when a result overflows.
+///
+/// Every operation normalizes its result, so a value has exactly one
+/// representation and equality and hashing don't depend on how it was made.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Int(Repr);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Small(i64),
+    /// Always outside the `i64` range.
+    Big(BigInt),
+}
+
+use Repr::{Big, Small};
+
+impl Int {
+    pub fn zero() -> Int {
+        Int(Small(0))
+    }
+
+    fn big(n: BigInt) -> Int {
+        match n.to_i64() {
+            Some(n) => Int(Small(n)),
+            None => Int(Big(n)),
+        }
+    }
+
+    /// Parses an optionally negative run of decimal digits.
+    pub fn parse(digits: &[u8]) -> Option<Int> {
+        let s = std::str::from_utf8(digits).ok()?;
+        match s.parse::<i64>() {
+            Ok(n) => Some(Int(Small(n))),
+            Err(_) => s.parse::<BigInt>().ok().map(Int::big),
+        }
+    }
+
+    pub fn to_bigint(&self) -> BigInt {
+        match &self.0 {
+            Small(n) => BigInt::from(*n),
+            Big(n) => n.clone(),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        matches!(self.0, Small(0))
+    }
+
+    pub fn is_positive(&self) -> bool {
+        match &self.0 {
+            Small(n) => *n > 0,
+            Big(n) => n.is_positive(),
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match &self.0 {
+            Small(n) => *n < 0,
+            Big(n) => n.is_negative(),
+        }
+    }
+
+    pub fn abs(&self) -> Int {
+        match &self.0 {
+            Small(n) => match n.checked_abs() {
+                Some(a) => Int(Small(a)),
+                None => Int::big(BigInt::from(*n).abs()),
+            },
+            Big(n) => Int::big(n.abs()),
+        }
+    }
+
+    /// The number of bits in the magnitude.
+    pub fn bits(&self) -> u64 {
+        match &self.0 {
+            Small(n) => u64::from(64 - n.unsigned_abs().leading_zeros()),
+            Big(n) => n.bits(),
+        }
+    }
+
+    /// Quotient and remainder, rounding the quotient toward negative
+    /// infinity. `other` must not be zero.
+    pub fn div_mod_floor(&self, other: &Int) -> (Int, Int) {
+        match (&self.0, &other.0) {
+            (Small(a), Small(b)) if *b != -1 => {
+                let (q, r) = a.div_mod_floor(b);
+                (Int(Small(q)), Int(Small(r)))
+            }
+            _ => {
+                let (q, r) = self.to_bigint().div_mod_floor(&other.to_bigint());
+                (Int::big(q), Int::big(r))
+            }
+        }
+    }
+
+    pub fn div_floor(&self, other: &Int) -> Int {
+        self.div_mod_floor(other).0
+    }
+
+    pub fn mod_floor(&self, other: &Int) -> Int {
+        self.div_mod_floor(other).1
+    }
+
+    pub fn pow(&self, e: u32) -> Int {
+        if let Small(a) = self.0 {
+            if let Some(p) = a.checked_pow(e) {
+                return Int(Small(p));
+            }
+        }
+        Int::big(num::pow(self.to_bigint(), e as usize))
+    }
+}
+
+impl ToPrimitive for Int {
+    fn to_i64(&self) -> Option<i64> {
+        match &self.0 {
+            Small(n) => Some(*n),
+            Big(_) => None,
+        }
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        match &self.0 {
+            Small(n) => n.to_u64(),
+            Big(n) => n.to_u64(),
+        }
+    }
+}
+
+impl From<i64> for Int {
+    fn from(n: i64) -> Int {
+        Int(Small(n))
+    }
+}
+
+impl From<i32> for Int {
+    fn from(n: i32) -> Int {
+        Int(Small(n.into()))
+    }
+}
+
+impl From<u8> for Int {
+    fn from(n: u8) -> Int {
+        Int(Small(n.into()))
+    }
+}
+
+impl From<u64> for Int {
+    fn from(n: u64) -> Int {
+        match i64::try_from(n) {
+            Ok(n) => Int(Small(n)),
+            Err(_) => Int(Big(n.into())),
+        }
+    }
+}
+
+impl From<usize> for Int {
+    fn from(n: usize) -> Int {
+        Int::from(n as u64)
+    }
+}
+
+impl From<BigInt> for Int {
+    fn from(n: BigInt) -> Int {
+        Int::big(n)
+    }
+}
+
+impl From<Int> for BigInt {
+    fn from(n: Int) -> BigInt {
+        match n.0 {
+            Small(n) => n.into(),
+            Big(n) => n,
+        }
+    }
+}
+
+impl Ord for Int {
+    fn cmp(&self, other: &Int) -> Ordering {
+        match (&self.0, &other.0) {
+            (Small(a), Small(b)) => a.cmp(b),
+            (Small(_), Big(b)) if b.is_positive() => Ordering::Less,
+            (Small(_), Big(_)) => Ordering::Greater,
+            (Big(a), Small(_)) if a.is_positive() => Ordering::Greater,
+            (Big(_), Small(_)) => Ordering::Less,
+            (Big(a), Big(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Int {
+    fn partial_cmp(&self, other: &Int) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Int {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Small(n) => n.fmt(f),
+            Big(n) => n.fmt(f),
+        }
+    }
+}
+
+impl fmt::Debug for Int {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Implements a binary operator on the inline values with `$checked`,
+/// falling back to `BigInt` when it returns `None`.
+macro_rules! binary_op {
+    ($trait:ident, $method:ident, $checked:expr) => {
+        impl $trait for Int {
+            type Output = Int;
+
+            fn $method(self, other: Int) -> Int {
+                if let (Small(a), Small(b)) = (&self.0, &other.0) {
+                    if let Some(r) = $checked(*a, *b) {
+                        return Int(Small(r));
+                    }
+                }
+                Int::big($trait::$method(BigInt::from(self), BigInt::from(other)))
+            }
+        }
+
+        impl $trait<i64> for Int {
+            type Output = Int;
+
+            fn $method(self, other: i64) -> Int {
+                $trait::$method(self, Int::from(other))
+            }
+        }
+    };
+}
+
+binary_op!(Add, add, i64::checked_add);
+binary_op!(Sub, sub, i64::checked_sub);
+binary_op!(Mul, mul, i64::checked_mul);
+binary_op!(BitAnd, bitand, |a: i64, b: i64| Some(a & b));
+binary_op!(BitOr, bitor, |a: i64, b: i64| Some(a | b));
+binary_op!(BitXor, bitxor, |a: i64, b: i64| Some(a ^ b));
+
+impl AddAssign<i64> for Int {
+    fn add_assign(&mut self, other: i64) {
+        *self = std::mem::replace(self, Int::zero()) + other;
+    }
+}
+
+impl SubAssign<i64> for Int {
+    fn sub_assign(&mut self, other: i64) {
+        *self = std::mem::replace(self, Int::zero()) - other;
+    }
+}
+
+impl Neg for Int {
+    type Output = Int;
+
+    fn neg(self) -> Int {
+        Int::zero() - self
+    }
+}
+
+impl Not for Int {
+    type Output = Int;
+
+    fn not(self) -> Int {
+        match self.0 {
+            Small(n) => Int(Small(!n)),
+            Big(n) => Int::big(!n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: i64 = i64::MAX;
+    const MIN: i64 = i64::MIN;
+
+    fn big(s: &str) -> Int {
+        let n: BigInt = s.parse().unwrap();
+        assert!(n.to_i64().is_none(), "{} fits in an i64", s);
+        Int(Big(n))
+    }
+
+    fn is_small(n: &Int) -> bool {
+        matches!(n.0, Small(_))
+    }
+
+    #[test]
+    fn add_overflows() {
+        assert_eq!(Int::from(MAX) + 1, big("9223372036854775808"));
+        assert_eq!(
+            Int::from(MIN) + Int::from(MIN),
+            big("-18446744073709551616")
+        );
+        assert_eq!(Int::from(MAX) + Int::from(MIN), Int::from(-1));
+    }
+
+    #[test]
+    fn sub_overflows() {
+        assert_eq!(Int::from(MIN) - 1, big("-9223372036854775809"));
+        assert_eq!(Int::from(MAX) - Int::from(MIN), big("18446744073709551615"));
+        assert_eq!(Int::from(MIN) - Int::from(MIN), Int::zero());
+    }
+
+    #[test]
+    fn mul_overflows() {
+        assert_eq!(Int::from(MAX) * 2, big("18446744073709551614"));
+        assert_eq!(Int::from(MIN) * -1, big("9223372036854775808"));
+        assert_eq!(Int::from(MIN) * 1, Int::from(MIN));
+    }
+
+    #[test]
+    fn div_mod_overflows() {
+        let (q, r) = Int::from(MIN).div_mod_floor(&Int::from(-1));
+        assert_eq!(q, big("9223372036854775808"));
+        assert_eq!(r, Int::zero());
+        assert!(is_small(&r));
+        assert_eq!(Int::from(MIN).div_floor(&Int::from(2)), Int::from(MIN / 2));
+        assert_eq!(Int::from(MAX).mod_floor(&Int::from(-2)), Int::from(-1));
+        assert_eq!(
+            big("9223372036854775808").div_floor(&Int::from(2)),
+            Int::from(1i64 << 62)
+        );
+    }
+
+    #[test]
+    fn pow_overflows() {
+        assert_eq!(Int::from(2).pow(63), big("9223372036854775808"));
+        assert_eq!(Int::from(-2).pow(63), Int::from(MIN));
+        assert!(is_small(&Int::from(-2).pow(63)));
+        assert_eq!(Int::from(2).pow(62), Int::from(1i64 << 62));
+    }
+
+    #[test]
+    fn neg_and_abs_overflow() {
+        assert_eq!(-Int::from(MIN), big("9223372036854775808"));
+        assert_eq!(Int::from(MIN).abs(), big("9223372036854775808"));
+        assert_eq!(-Int::from(MAX), Int::from(-MAX));
+        assert_eq!(-big("9223372036854775808"), Int::from(MIN));
+    }
+
+    #[test]
+    fn demotes_to_small() {
+        let n = big("9223372036854775808") - 1;
+        assert!(is_small(&n));
+        assert_eq!(n, Int::from(MAX));
+        let n = big("-9223372036854775809") + 1;
+        assert!(is_small(&n));
+        assert_eq!(n, Int::from(MIN));
+        assert!((Int::from(MAX) + 1 - (Int::from(MAX) + 1)).is_zero());
+        assert!(is_small(&Int::from(BigInt::from(5))));
+    }
+
+    #[test]
+    fn parses_at_the_boundaries() {
+        assert_eq!(Int::parse(b"9223372036854775807"), Some(Int::from(MAX)));
+        assert_eq!(Int::parse(b"-9223372036854775808"), Some(Int::from(MIN)));
+        assert_eq!(
+            Int::parse(b"9223372036854775808"),
+            Some(big("9223372036854775808"))
+        );
+        assert_eq!(Int::from(u64::MAX), big("18446744073709551615"));
+    }
+
+    #[test]
+    fn compares_across_representations() {
+        let above = big("9223372036854775808");
+        let below = big("-9223372036854775809");
+        assert!(Int::from(MAX) < above);
+        assert!(Int::from(MIN) > below);
+        assert!(below < above);
+    }
+}
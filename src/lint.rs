@@ -0,0 +1,282 @@
+use crate::analyze::{analyze, Warning};
+use crate::code::Builtin;
+use crate::format::is_whitespace;
+use crate::parse::{parse_code, Gtoken, Span, Token};
+use std::collections::HashSet;
+
+/// Looks for likely mistakes in `source`: names that are never assigned,
+/// assignments that shadow a builtin or are never used, `[` and `]` that
+/// don't pair up, source that doesn't parse, `:` with nothing after it,
+/// `if` branches that can't run, and what [`analyze`] warns about. The
+/// warnings are in source order.
+///
+/// ```
+/// let warnings = golfscript_rs::lint(b"1:n;0{x 1}{2}if");
+/// let messages: Vec<_> = warnings.iter().map(|w| w.message.as_str()).collect();
+/// assert_eq!(
+///     messages,
+///     [
+///         "assigning `n` shadows the builtin",
+///         "`n` is assigned but never used",
+///         "the condition is always false, so this branch never runs",
+///         "`x` is never assigned, so it does nothing",
+///     ]
+/// );
+/// ```
+pub fn lint(source: &[u8]) -> Vec<Warning> {
+    let mut lint = Lint::default();
+    let tokens = match parse_code(source) {
+        Ok((rest, tokens)) => {
+            if let Some(&b) = rest.first() {
+                let message = match b {
+                    b'}' => "`}` without a `{`",
+                    b'{' => "unterminated block",
+                    _ => "unterminated string",
+                };
+                let offset = source.len() - rest.len();
+                lint.warn(Span::point(source, offset), message.to_owned());
+            }
+            tokens
+        }
+        Err(_) => vec![],
+    };
+    lint.names(&tokens);
+    for string in &lint.strings.clone() {
+        if let Ok((_, tokens)) = parse_code(string) {
+            lint.quoted(&tokens);
+        }
+    }
+    lint.check(&tokens, true);
+    if let Ok(analysis) = analyze(source) {
+        lint.warnings.extend(analysis.warnings);
+    }
+    lint.warnings.sort_by_key(|w| (w.span.start, w.span.end));
+    lint.warnings
+}
+
+#[derive(Default)]
+struct Lint {
+    /// Each name assigned, with where it first is.
+    assigned: Vec<(Vec<u8>, Span)>,
+    used: HashSet<Vec<u8>>,
+    /// Names assigned only by strings, which have no span in the source.
+    quoted_assigned: HashSet<Vec<u8>>,
+    /// The contents of the string literals, which may be run with `~`.
+    strings: Vec<Vec<u8>>,
+    warnings: Vec<Warning>,
+}
+
+impl Lint {
+    fn warn(&mut self, span: Span, message: String) {
+        self.warnings.push(Warning { span, message });
+    }
+
+    /// Finds the names assigned and used in `tokens`.
+    fn names(&mut self, tokens: &[Token]) {
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            match &token.gtoken {
+                Gtoken::Symbol(b":") => {
+                    let Some(name) = tokens.next() else {
+                        continue;
+                    };
+                    let lexeme = name.gtoken.lexeme();
+                    let span = Span {
+                        end: name.span.end,
+                        ..token.span
+                    };
+                    if !self.assigned.iter().any(|a| a.0 == lexeme) {
+                        self.assigned.push((lexeme.to_vec(), span));
+                    }
+                    if let Gtoken::Block(inner, _) = &name.gtoken {
+                        self.names(inner);
+                    }
+                }
+                Gtoken::Symbol(name) => {
+                    self.used.insert(name.to_vec());
+                }
+                Gtoken::SingleQuotedString(s) | Gtoken::DoubleQuotedString(s) => {
+                    self.strings.push(s[1..s.len() - 1].to_vec());
+                }
+                Gtoken::Block(inner, _) => self.names(inner),
+                _ => {}
+            }
+        }
+    }
+
+    /// Counts the names a string mentions as used, or assigns.
+    fn quoted(&mut self, tokens: &[Token]) {
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            match &token.gtoken {
+                Gtoken::Symbol(b":") => {
+                    if let Some(name) = tokens.next() {
+                        self.quoted_assigned.insert(name.gtoken.lexeme().to_vec());
+                    }
+                }
+                Gtoken::Symbol(name) => {
+                    self.used.insert(name.to_vec());
+                }
+                Gtoken::Block(inner, _) => self.quoted(inner),
+                _ => {}
+            }
+        }
+    }
+
+    fn check(&mut self, tokens: &[Token], top: bool) {
+        if top {
+            for (name, span) in self.assigned.clone() {
+                if Builtin::from_name(&name).is_some() {
+                    let message = format!("assigning `{}` shadows the builtin", lossy(&name));
+                    self.warn(span, message);
+                }
+                if !self.used.contains(&name) {
+                    let message = format!("`{}` is assigned but never used", lossy(&name));
+                    self.warn(span, message);
+                }
+            }
+        }
+        let mut brackets = vec![];
+        let mut iter = tokens.iter().enumerate();
+        while let Some((i, token)) = iter.next() {
+            match &token.gtoken {
+                Gtoken::Symbol(b":") => match iter.next() {
+                    Some((_, name)) => {
+                        if let Gtoken::Block(inner, _) = &name.gtoken {
+                            self.check(inner, false);
+                        }
+                    }
+                    None => self.warn(token.span, "`:` with nothing to assign to".to_owned()),
+                },
+                Gtoken::Symbol(b"[") => brackets.push(token.span),
+                // a program's `]` without a `[`, as in `~]`, is a common way
+                // to collect the whole stack
+                Gtoken::Symbol(b"]") => {
+                    let opened = brackets.pop().is_some();
+                    if !opened && !top {
+                        self.warn(token.span, "`]` without a `[` in its block".to_owned());
+                    }
+                }
+                Gtoken::Symbol(b"if") => self.check_if(&tokens[..i]),
+                Gtoken::Symbol(name)
+                    if !is_whitespace(name)
+                        && !self.is_assigned(name)
+                        && Builtin::from_name(name).is_none() =>
+                {
+                    let message =
+                        format!("`{}` is never assigned, so it does nothing", lossy(name));
+                    self.warn(token.span, message);
+                }
+                Gtoken::Block(inner, _) => self.check(inner, false),
+                _ => {}
+            }
+        }
+        for span in brackets {
+            self.warn(span, "`[` without a `]`".to_owned());
+        }
+    }
+
+    fn is_assigned(&self, name: &[u8]) -> bool {
+        self.assigned.iter().any(|a| a.0 == name) || self.quoted_assigned.contains(name)
+    }
+
+    /// Looks at the three values before an `if`, for a condition that is a
+    /// literal.
+    fn check_if(&mut self, before: &[Token]) {
+        let mut values = before.iter().rev().filter(|t| match &t.gtoken {
+            Gtoken::Symbol(s) => !is_whitespace(s),
+            Gtoken::Comment(_) => false,
+            _ => true,
+        });
+        let (Some(otherwise), Some(then), Some(cond)) =
+            (values.next(), values.next(), values.next())
+        else {
+            return;
+        };
+        // even a literal runs a variable if one has its name
+        if self.is_assigned(cond.gtoken.lexeme()) {
+            return;
+        }
+        let truthy = match &cond.gtoken {
+            Gtoken::IntLiteral(n) => n.iter().any(|&b| b.is_ascii_digit() && b != b'0'),
+            Gtoken::SingleQuotedString(s) | Gtoken::DoubleQuotedString(s) => s.len() > 2,
+            Gtoken::Block(_, src) => !src.is_empty(),
+            _ => return,
+        };
+        let (dead, how) = if truthy {
+            (otherwise, "true")
+        } else {
+            (then, "false")
+        };
+        if !matches!(dead.gtoken, Gtoken::Block(..)) {
+            return;
+        }
+        let message = format!("the condition is always {}, so this branch never runs", how);
+        self.warn(dead.span, message);
+    }
+}
+
+fn lossy(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &[u8]) -> Vec<String> {
+        lint(source).into_iter().map(|w| w.message).collect()
+    }
+
+    #[test]
+    fn unterminated_strings_and_blocks() {
+        assert_eq!(messages(b"1 \"abc"), ["unterminated string"]);
+        assert_eq!(messages(b"1 'abc"), ["unterminated string"]);
+        assert_eq!(messages(b"1{2"), ["unterminated block"]);
+        assert_eq!(messages(b"1}2"), ["`}` without a `{`"]);
+        let warning = &lint(b"1\n  \"abc")[0];
+        assert_eq!((warning.span.line, warning.span.col), (2, 3));
+    }
+
+    #[test]
+    fn brackets() {
+        // collecting the whole stack is fine in a program
+        assert!(messages(b"~]").is_empty());
+        assert_eq!(messages(b"1 2{]}:f;f"), ["`]` without a `[` in its block"]);
+        assert_eq!(messages(b"[1 2"), ["`[` without a `]`"]);
+        assert!(messages(b"[1{[2]}%]").is_empty());
+    }
+
+    #[test]
+    fn names_used_from_strings() {
+        assert_eq!(messages(b"1:x;"), ["`x` is assigned but never used"]);
+        assert!(messages(b"1:x;'x 2+'~").is_empty());
+        assert!(messages(b"{.}:f;\"{f}%\"~").is_empty());
+        assert!(messages(b"\"1:x;\"~x").is_empty());
+        assert!(messages(b"'1:c;'~c{2}{3}if").is_empty());
+    }
+
+    #[test]
+    fn undefined_and_shadowing() {
+        assert_eq!(
+            messages(b"x"),
+            ["`x` is never assigned, so it does nothing"]
+        );
+        assert_eq!(messages(b"{1}:p;p"), ["assigning `p` shadows the builtin"]);
+    }
+
+    #[test]
+    fn dead_branches() {
+        assert_eq!(
+            messages(b"1{2}{3}if"),
+            ["the condition is always true, so this branch never runs"]
+        );
+        assert!(messages(b"1:c;c{2}{3}if").is_empty());
+        assert!(messages(b"{2}{3}if").is_empty());
+    }
+
+    #[test]
+    fn trailing_colon() {
+        assert_eq!(messages(b"1:"), ["`:` with nothing to assign to"]);
+    }
+}
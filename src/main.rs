@@ -1,777 +1,352 @@
-use crate::coerce::flatten;
-use crate::parse::parse_code;
-use crate::util::chunk;
-use crate::util::every_nth;
-use crate::util::index;
-use crate::util::slice;
-use crate::util::split;
-use crate::util::string_index;
-use crate::value::join;
 use clap::Parser;
-use num::BigInt;
-use num::Integer;
-use num::One;
-use num::Signed;
-use num::ToPrimitive;
-use num::Zero;
-use std::cmp::Ordering;
-use std::io::Read;
-use std::io::Write;
+use golfscript_rs::{
+    format, minify, Case, Compare, Config, Coverage, Effect, Gs, GsError, Gval, Limits, Profiler,
+    Sink, Tracer,
+};
+use std::io::{Read, Write};
 
-use std::collections::HashMap;
+mod repl;
 
-mod coerce;
-mod parse;
-mod unescape;
-mod util;
-mod value;
-
-use crate::coerce::{coerce, Coerced};
-use crate::parse::Gtoken;
-use crate::unescape::unescape;
-use crate::util::{repeat, set_and, set_or, set_subtract, set_xor};
-use crate::value::Gval;
-
-fn print(bytes: &[u8]) {
-    std::io::stdout().write_all(bytes).unwrap();
+#[derive(clap::Parser, Debug)]
+#[clap(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(long)]
+    code_path: Option<String>,
+    #[clap(short = 'e', long, allow_hyphen_values = true)]
+    code: Option<String>,
+    #[clap(long)]
+    input_path: Option<String>,
+    #[clap(short = 'i', long, allow_hyphen_values = true)]
+    input: Option<String>,
+    #[clap(short = 'q', long, takes_value = false)]
+    no_implicit_output: bool,
+    #[clap(short = 's', long, takes_value = false)]
+    input_from_stdin: bool,
+    /// Leave stdin unread for the `gets` and `read` builtins, and start
+    /// with an empty string on the stack
+    #[clap(long, takes_value = false)]
+    stream_stdin: bool,
+    #[clap(long, takes_value = false)]
+    args: bool,
+    /// Log every token run, with the stack after it, to stderr
+    #[clap(long, takes_value = false)]
+    trace: bool,
+    /// Log the trace as JSON lines
+    #[clap(long, takes_value = false)]
+    trace_json: bool,
+    /// Cut values in the trace to this many bytes
+    #[clap(long)]
+    trace_width: Option<usize>,
+    /// Time the builtins, variables and blocks run, and print a report to
+    /// stderr
+    #[clap(long, takes_value = false, conflicts_with_all = &["trace", "trace-json"])]
+    profile: bool,
+    /// Write the profile to this file as JSON instead
+    #[clap(long, conflicts_with_all = &["trace", "trace-json"])]
+    profile_json: Option<String>,
+    /// Count how often each token and block ran, and print the source with
+    /// the counts to stderr
+    #[clap(long, takes_value = false, conflicts_with_all = &["trace", "trace-json", "profile", "profile-json"])]
+    coverage: bool,
+    /// Write the coverage to this file in the lcov format instead
+    #[clap(long, conflicts_with_all = &["trace", "trace-json", "profile", "profile-json"])]
+    coverage_lcov: Option<String>,
+    /// Also run the program on this input, for coverage only; its output is
+    /// discarded. Can be given more than once
+    #[clap(long, multiple_occurrences = true)]
+    coverage_input: Vec<String>,
+    /// Start an interactive session instead of running code
+    #[clap(long, takes_value = false)]
+    repl: bool,
+    /// Stop after executing this many tokens and loop iterations
+    #[clap(long)]
+    max_steps: Option<u64>,
+    /// Stop after this many milliseconds
+    #[clap(long)]
+    max_time_ms: Option<u64>,
+    /// Stop when the stack and variables hold about this many bytes
+    #[clap(long)]
+    max_heap: Option<usize>,
+    /// Stop when blocks nest this deep
+    #[clap(long)]
+    max_depth: Option<usize>,
+    /// Stop when the stack holds this many values
+    #[clap(long)]
+    max_stack: Option<usize>,
+    /// Stop before printing more than this many bytes
+    #[clap(long)]
+    max_output: Option<usize>,
+    args_vec: Vec<String>,
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-struct Gs {
-    pub stack: Vec<Gval>,
-    vars: HashMap<Vec<u8>, Gval>,
-    lb: Vec<usize>,
-    rng_state: u64,
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Lay a program out for reading, a statement per line with the blocks
+    /// that only run indented, and print it
+    Fmt {
+        /// The program; stdin if left out
+        path: Option<String>,
+        /// Rewrite the file instead of printing it
+        #[clap(short, long, takes_value = false, requires = "path")]
+        write: bool,
+    },
+    /// Work out how each block and the program change the stack, and warn
+    /// about branches and loops that leave the wrong number of values
+    Analyze {
+        /// The program; stdin if left out
+        path: Option<String>,
+    },
+    /// Warn about likely mistakes, such as names never assigned or
+    /// variables never used; exits with 1 if there are any
+    Lint {
+        /// The program; stdin if left out
+        path: Option<String>,
+    },
+    /// Drop comments and spacing and shorten variable names, and print the
+    /// result
+    Minify {
+        /// The program; stdin if left out
+        path: Option<String>,
+        /// Rewrite the file instead of printing it
+        #[clap(short, long, takes_value = false, requires = "path")]
+        write: bool,
+    },
+    /// Run each NAME.gs in a directory on NAME.in, if there is one, and
+    /// compare what it prints with NAME.out; exits with 1 if any differ. A
+    /// NAME.judge can set `compare` and `timeout-ms` for a case
+    Test {
+        /// The directory of cases
+        dir: String,
+        /// Compare output exactly, ignoring trailing whitespace, or treating
+        /// CRLF as LF, for cases that don't say
+        #[clap(long, default_value = "exact", possible_values = ["exact", "trailing-whitespace", "crlf"])]
+        compare: Compare,
+        /// Write what each case prints to its NAME.out instead of comparing,
+        /// unless it stops with an error
+        #[clap(long, takes_value = false)]
+        bless: bool,
+        /// Stop each case after this many milliseconds, unless it says
+        #[clap(long, default_value = "10000")]
+        max_time_ms: u64,
+        /// Stop each case before printing more than this many bytes
+        #[clap(long, default_value = "1048576")]
+        max_output: usize,
+        /// List the cases that pass too
+        #[clap(short, long, takes_value = false)]
+        verbose: bool,
+    },
 }
 
-impl Gs {
-    pub fn new() -> Gs {
-        Gs {
-            stack: vec![],
-            vars: HashMap::new(),
-            lb: vec![],
-            rng_state: 123456789u64,
-        }
-    }
-
-    pub fn run(&mut self, code: &[u8]) {
-        let (rest, tokens) = parse_code(code).expect("parse error");
-        if rest.len() > 0 {
-            panic!("parse error: has remainder")
-        }
-        // println!("parse: {:?}", tokens);
-        let mut tokens = tokens.into_iter();
-        while let Some(token) = tokens.next() {
-            match token {
-                Gtoken::Symbol(b":") => {
-                    let name = tokens.next().expect("parse error: assignment");
-                    let t = self.top().clone();
-                    self.vars.insert(name.lexeme().to_owned(), t);
-                }
-                t => {
-                    self.run_token(t);
-                }
-            }
-        }
-    }
-
-    fn push(&mut self, val: Gval) {
-        self.stack.push(val)
-    }
-
-    fn top(&self) -> &Gval {
-        self.stack.last().expect("stack underflow")
-    }
-
-    fn dup(&mut self) {
-        let a = self.pop();
-        self.push(a.clone());
-        self.push(a);
+fn config(cli: &Cli) -> Config {
+    Config {
+        limits: Limits {
+            steps: cli.max_steps,
+            time: cli.max_time_ms.map(std::time::Duration::from_millis),
+            heap: cli.max_heap,
+            depth: cli.max_depth,
+            stack: cli.max_stack,
+            output: cli.max_output,
+        },
+        ..Config::strict()
     }
+}
 
-    fn pop(&mut self) -> Gval {
-        let mut i = self.lb.len();
-        while i > 0 && self.lb[i - 1] >= self.stack.len() {
-            i -= 1;
-            if self.lb[i] > 0 {
-                self.lb[i] -= 1;
-            }
-        }
-        self.stack.pop().expect("stack underflow")
-    }
-
-    fn tilde(&mut self) {
-        match self.pop() {
-            Gval::Int(n) => self.push(Gval::Int(!n)),
-            Gval::Arr(vs) => self.stack.extend(vs),
-            Gval::Str(bs) => self.run(&bs),
-            Gval::Blk(bs) => self.run(&bs),
-        }
-    }
-
-    fn backtick(&mut self) {
-        let bs = self.pop().inspect();
-        self.push(Gval::Str(bs));
-    }
-
-    fn bang(&mut self) {
-        let f = self.pop().falsey();
-        self.push(Gval::bool(f));
-    }
-
-    fn at_sign(&mut self) {
-        let c = self.pop();
-        let b = self.pop();
-        let a = self.pop();
-        self.push(b);
-        self.push(c);
-        self.push(a);
-    }
-
-    fn dollar(&mut self) {
-        match self.pop() {
-            Gval::Int(n) => {
-                let len: BigInt = self.stack.len().into();
-                if n < (-1i32).into() {
-                    if let Some(i) = (-n - 2i32).to_usize() {
-                        if i < self.stack.len() {
-                            self.push(self.stack[i].clone());
-                        }
-                    }
-                } else if n >= 0i32.into() && n < len {
-                    if let Some(i) = (len - 1i32 - n).to_usize() {
-                        self.push(self.stack[i].clone());
-                    }
-                }
-            }
-            Gval::Arr(mut vs) => {
-                vs.sort();
-                self.push(Gval::Arr(vs));
-            }
-            Gval::Str(mut bs) => {
-                bs.sort();
-                self.push(Gval::Str(bs));
-            }
-            Gval::Blk(code) => match self.pop() {
-                Gval::Int(_) => panic!("can't sort an integer"),
-                Gval::Arr(vs) => {
-                    let sorted = self.sort_by(code, vs);
-                    self.push(Gval::Arr(sorted));
-                }
-                Gval::Str(vs) => {
-                    let sorted = self.sort_by(code, vs);
-                    self.push(Gval::Str(sorted));
-                }
-                Gval::Blk(vs) => {
-                    let sorted = self.sort_by(code, vs);
-                    self.push(Gval::Blk(sorted));
-                }
-            },
-        }
-    }
-
-    fn sort_by<T: Ord + Clone + Into<Gval>>(&mut self, code: Vec<u8>, vs: Vec<T>) -> Vec<T> {
-        let mut results: Vec<(Gval, T)> = vec![];
-        for v in vs {
-            self.push(v.clone().into());
-            self.run(&code);
-            results.push((self.pop(), v));
-        }
-        results.sort_by(|a, b| a.0.cmp(&b.0));
-        results.into_iter().map(|x| x.1).collect()
-    }
-
-    fn plus(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        self.push(a.plus(b));
-    }
-
-    fn minus(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        match coerce(a, b) {
-            Coerced::Ints(x, y) => self.push(Gval::Int(x - y)),
-            Coerced::Arrs(x, y) => self.push(Gval::Arr(set_subtract(x, y))),
-            Coerced::Strs(x, y) => self.push(Gval::Str(set_subtract(x, y))),
-            Coerced::Blks(x, y) => self.push(Gval::Blk(set_subtract(x, y))),
-        }
-    }
-
-    fn asterisk(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        use Gval::*;
-        match (a, b) {
-            // multiply
-            (Int(a), Int(b)) => self.push(Int(a * b)),
-            // join
-            (Arr(a), Arr(sep)) => self.push(join(a, Arr(sep))),
-            (Arr(a), Str(sep)) | (Str(sep), Arr(a)) => self.push(join(a, Str(sep))),
-            (Str(a), Str(sep)) => {
-                let a: Vec<Gval> = a.into_iter().map(|x| Gval::Str(vec![x.into()])).collect();
-                self.push(join(a, Str(sep)));
-            }
-
-            // fold
-            (Blk(code), Blk(a)) | (Str(a), Blk(code)) | (Blk(code), Str(a)) => self.fold(code, a),
-            (Arr(a), Blk(code)) | (Blk(code), Arr(a)) => self.fold(code, a),
-
-            // repeat
-            (Int(n), Arr(a)) | (Arr(a), Int(n)) => self.push(Arr(repeat(a, n))),
-            (Int(n), Str(a)) | (Str(a), Int(n)) => self.push(Str(repeat(a, n))),
-
-            // times
-            (Int(mut n), Blk(f)) | (Blk(f), Int(mut n)) => {
-                while n.is_positive() {
-                    self.run(&f);
-                    n -= 1;
-                }
-            }
-        }
-    }
-
-    fn slash(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        use Gval::*;
-        match (a, b) {
-            // divide
-            (Int(a), Int(b)) => self.push(Int(a.div_floor(&b))),
-            // split
-            (Arr(a), Arr(sep)) => {
-                let s = split(a, sep, false);
-                self.push(Arr(s.into_iter().map(|x| Arr(x)).collect()));
-            }
-            (Str(a), Str(sep)) => {
-                let s = split(a, sep, false);
-                self.push(Arr(s.into_iter().map(|x| Str(x)).collect()));
-            }
-            (Arr(a), Str(sep)) | (Str(sep), Arr(a)) => {
-                let s = split(a, sep.into_iter().map(|x| x.into()).collect(), false);
-                self.push(Arr(s.into_iter().map(|x| Arr(x)).collect()));
-            }
-
-            // each
-            (Str(a), Blk(code)) | (Blk(code), Str(a)) => self.each(code, a),
-            (Arr(a), Blk(code)) | (Blk(code), Arr(a)) => self.each(code, a),
-
-            // chunk
-            (Int(n), Arr(mut a)) | (Arr(mut a), Int(n)) => {
-                let c = chunk(&mut a, n);
-                self.push(Arr(c.into_iter().map(|x| Arr(x.to_owned())).collect()));
-            }
-            (Int(n), Str(mut a)) | (Str(mut a), Int(n)) => {
-                let c = chunk(&mut a, n);
-                self.push(Arr(c.into_iter().map(|x| Str(x.to_owned())).collect()));
-            }
-
-            // unfold
-            (Blk(cond), Blk(step)) => {
-                let mut r = vec![];
-                loop {
-                    self.push(self.top().clone());
-                    self.run(&cond);
-                    if self.pop().falsey() {
-                        break;
-                    }
-                    r.push(self.top().clone());
-                    self.run(&step);
-                }
-                self.pop();
-                self.push(Gval::Arr(r));
-            }
-
-            (Blk(_), Int(_)) | (Int(_), Blk(_)) => {
-                panic!("int-block /")
-            }
-        }
-    }
-
-    fn percent(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        use Gval::*;
-        match (a, b) {
-            // modulo
-            (Int(a), Int(b)) => self.push(Int(a.mod_floor(&b))),
-            // clean split
-            (Arr(a), Arr(sep)) => {
-                let s = split(a, sep, true);
-                self.push(Arr(s.into_iter().map(|x| Arr(x)).collect()));
-            }
-            (Str(a), Str(sep)) => {
-                let s = split(a, sep, true);
-                self.push(Arr(s.into_iter().map(|x| Str(x)).collect()));
-            }
-            (Arr(a), Str(sep)) | (Str(sep), Arr(a)) => {
-                let s = split(a, sep.into_iter().map(|x| x.into()).collect(), true);
-                self.push(Arr(s.into_iter().map(|x| Arr(x)).collect()));
-            }
-
-            // map
-            (Arr(a), Blk(code)) | (Blk(code), Arr(a)) => {
-                let r = self.gs_map(code, a);
-                self.push(Arr(r))
-            }
-            (Str(a), Blk(code)) | (Blk(code), Str(a)) => {
-                let r = self.gs_map(code, a);
-                self.push(Str(flatten(r)))
-            }
-
-            // every nth
-            (Int(n), Arr(a)) | (Arr(a), Int(n)) => self.push(Arr(every_nth(a, n))),
-            (Int(n), Str(a)) | (Str(a), Int(n)) => self.push(Str(every_nth(a, n))),
-
-            // unimplemented
-            (Int(_), Blk(_)) | (Blk(_), Int(_)) | (Blk(_), Blk(_)) => panic!("%"),
+/// The program at `path`, or on stdin.
+fn read_source(path: &Option<String>) -> Vec<u8> {
+    match path {
+        Some(path) => std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("error: can't read {}: {}", path, e);
+            std::process::exit(1)
+        }),
+        None => {
+            let mut bytes = vec![];
+            std::io::stdin().read_to_end(&mut bytes).unwrap();
+            bytes
         }
     }
+}
 
-    fn vertical_bar(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        self.push(match coerce(a, b) {
-            Coerced::Ints(x, y) => Gval::Int(x | y),
-            Coerced::Arrs(x, y) => Gval::Arr(set_or(x, y)),
-            Coerced::Strs(x, y) => Gval::Str(set_or(x, y)),
-            Coerced::Blks(x, y) => Gval::Blk(set_or(x, y)),
-        })
-    }
-
-    fn ampersand(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        self.push(match coerce(a, b) {
-            Coerced::Ints(x, y) => Gval::Int(x & y),
-            Coerced::Arrs(x, y) => Gval::Arr(set_and(x, y)),
-            Coerced::Strs(x, y) => Gval::Str(set_and(x, y)),
-            Coerced::Blks(x, y) => Gval::Blk(set_and(x, y)),
-        })
+/// Rewrites the program at `path` with `how`, for `fmt` and `minify`.
+fn rewrite(path: &Option<String>, write: bool, how: fn(&[u8]) -> Result<Vec<u8>, GsError>) {
+    let source = read_source(path);
+    match how(&source) {
+        Ok(rewritten) if write => std::fs::write(path.as_ref().unwrap(), rewritten).unwrap(),
+        Ok(rewritten) => std::io::stdout().write_all(&rewritten).unwrap(),
+        Err(e) => report(&e, &source),
     }
+}
 
-    fn caret(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        self.push(match coerce(a, b) {
-            Coerced::Ints(x, y) => Gval::Int(x ^ y),
-            Coerced::Arrs(x, y) => Gval::Arr(set_xor(x, y)),
-            Coerced::Strs(x, y) => Gval::Str(set_xor(x, y)),
-            Coerced::Blks(x, y) => Gval::Blk(set_xor(x, y)),
-        })
-    }
-
-    fn lteqgt(&mut self, ordering: Ordering) {
-        let b = self.pop();
-        let a = self.pop();
-        use Gval::*;
-        use Ordering::*;
-        match (ordering, a, b) {
-            (Equal, Int(i), Arr(a)) | (Equal, Arr(a), Int(i)) => {
-                index(&a, i).map(|x| self.push(x.clone()));
-            }
-            (Equal, Int(i), Str(a))
-            | (Equal, Str(a), Int(i))
-            | (Equal, Int(i), Blk(a))
-            | (Equal, Blk(a), Int(i)) => {
-                index(&a, i).map(|x| self.push(x.clone().into()));
-            }
-            (o, Int(i), Arr(a)) | (o, Arr(a), Int(i)) => self.push(Arr(slice(o, a, i))),
-            (o, Int(i), Str(a)) | (o, Str(a), Int(i)) => self.push(Str(slice(o, a, i))),
-            (o, Int(i), Blk(a)) | (o, Blk(a), Int(i)) => self.push(Blk(slice(o, a, i))),
-            (o, x, y) => self.push(Gval::bool(x.cmp(&y) == o)),
-        }
+/// Reports an error in `source` and exits.
+fn report(e: &GsError, source: &[u8]) {
+    eprintln!("error: {}", e);
+    if let Some(excerpt) = e.excerpt(source) {
+        eprintln!("{}", excerpt);
     }
+    std::process::exit(1);
+}
 
-    fn comma(&mut self) {
-        use Gval::*;
-        match self.pop() {
-            Int(n) => {
-                let mut r = vec![];
-                let mut i = BigInt::zero();
-                while i < n {
-                    r.push(Int(i.clone()));
-                    i += 1i32;
-                }
-                self.push(Arr(r));
-            }
-            Arr(a) => self.push(a.len().into()),
-            Str(a) => self.push(a.len().into()),
-            Blk(code) => match self.pop() {
-                Int(_) => panic!("select on integer"),
-                Arr(a) => {
-                    let r = self.select(code, a);
-                    self.push(Arr(r))
-                }
-                Str(a) => {
-                    let r = self.select(code, a);
-                    self.push(Str(r))
-                }
-                Blk(a) => {
-                    let r = self.select(code, a);
-                    self.push(Blk(r))
-                }
-            },
+fn analyze(path: &Option<String>) {
+    let source = read_source(path);
+    let analysis = match golfscript_rs::analyze(&source) {
+        Ok(analysis) => analysis,
+        Err(e) => return report(&e, &source),
+    };
+    let effect = |e: Option<Effect>| e.map_or("(?)".to_owned(), |e| e.to_string());
+    for (span, e) in analysis.blocks {
+        let block = String::from_utf8_lossy(&source[span.start..span.end]);
+        let mut block: String = block
+            .lines()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(40)
+            .collect();
+        if block.len() < span.end - span.start {
+            block.push_str("...");
         }
+        println!("{}:{}: {} {}", span.line, span.col, block, effect(e));
     }
-
-    fn question(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
-        use Gval::*;
-        match (a, b) {
-            // power
-            (Int(a), Int(b)) => self.push(Int(match b.to_u32() {
-                Some(e) => a.pow(e),
-                None => BigInt::zero(),
-            })),
-
-            // indexof
-            (Arr(h), n @ Int(_))
-            | (n @ Int(_), Arr(h))
-            | (Arr(h), n @ Str(_))
-            | (n @ Str(_), Arr(h))
-            | (Arr(h), n @ Arr(_)) => self.push(Gval::Int(
-                h.iter()
-                    .position(|x| *x == n)
-                    .map_or(-BigInt::one(), BigInt::from),
-            )),
-            (Str(h), Int(n)) | (Int(n), Str(h)) => self.push(Gval::Int(match n.to_u8() {
-                None => -BigInt::one(),
-                Some(b) => h
-                    .iter()
-                    .position(|x| *x == b)
-                    .map_or(-BigInt::one(), BigInt::from),
-            })),
-            (Str(h), Str(n)) => self.push(Gval::Int(string_index(&h, &n))),
-
-            // find
-            (Int(_), Blk(_)) | (Blk(_), Int(_)) => panic!(),
-            (Blk(code), Blk(a)) | (Blk(code), Str(a)) | (Str(a), Blk(code)) => self.find(code, a),
-            (Blk(code), Arr(a)) | (Arr(a), Blk(code)) => self.find(code, a),
-        }
+    println!("program {}", effect(analysis.program));
+    for warning in analysis.warnings {
+        eprintln!(
+            "{}:{}: warning: {}",
+            warning.span.line, warning.span.col, warning.message
+        );
     }
+}
 
-    fn left_paren(&mut self) {
-        use Gval::*;
-        match self.pop() {
-            Int(n) => self.push(Int(n - 1i32)),
-            Arr(a) => {
-                self.push(Arr(a[1..].to_vec()));
-                self.push(a[0].clone());
-            }
-            Str(a) => {
-                self.push(Str(a[1..].to_vec()));
-                self.push(a[0].into());
-            }
-            Blk(a) => {
-                self.push(Blk(a[1..].to_vec()));
-                self.push(a[0].into());
-            }
-        }
+fn lint(path: &Option<String>) {
+    let source = read_source(path);
+    let warnings = golfscript_rs::lint(&source);
+    let name = path.as_deref().unwrap_or("<stdin>");
+    for warning in &warnings {
+        eprintln!(
+            "{}:{}:{}: warning: {}",
+            name, warning.span.line, warning.span.col, warning.message
+        );
     }
-
-    fn right_paren(&mut self) {
-        use Gval::*;
-        match self.pop() {
-            Int(n) => self.push(Int(n + 1i32)),
-            Arr(mut a) => {
-                let l = a.pop().unwrap();
-                self.push(Arr(a.to_vec()));
-                self.push(l);
-            }
-            Str(mut a) => {
-                let l = a.pop().unwrap();
-                self.push(Str(a.to_vec()));
-                self.push(l.into());
-            }
-            Blk(mut a) => {
-                let l = a.pop().unwrap();
-                self.push(Blk(a.to_vec()));
-                self.push(l.into());
-            }
-        }
+    if !warnings.is_empty() {
+        std::process::exit(1);
     }
+}
 
-    fn rng(&mut self) -> u64 {
-        let (m, _) = self.rng_state.overflowing_mul(1664525);
-        let (m, _) = m.overflowing_add(1013904223);
-        self.rng_state = m;
-        self.rng_state
-    }
+struct Judging {
+    limits: Limits,
+    compare: Compare,
+    bless: bool,
+    verbose: bool,
+}
 
-    fn rand(&mut self) {
-        let r = match self.pop() {
-            Gval::Int(n) if n.is_positive() => self.rng() % n,
-            _ => BigInt::zero(),
+fn test(dir: &str, judging: Judging) {
+    let mut cases = Case::load_dir(dir.as_ref()).unwrap_or_else(|e| {
+        eprintln!("error: can't read the cases in {}: {}", dir, e);
+        std::process::exit(1)
+    });
+    let mut passed = 0;
+    for case in &mut cases {
+        // judge what running the program prints, so no lenient loop caps
+        let outcome = case.run(Config {
+            limits: judging.limits,
+            ..Config::strict()
+        });
+        let mismatch = if outcome.timed_out() {
+            Some(format!("timed out after {:.2?}", outcome.elapsed))
+        } else {
+            case.mismatch(&outcome.output, judging.compare)
         };
-        self.push(Gval::Int(r));
-    }
-
-    fn do_loop(&mut self) {
-        let a = self.pop();
-        loop {
-            self.go(a.clone());
-            if self.pop().falsey() {
-                break;
-            }
-        }
-    }
-
-    fn while_loop(&mut self, which: bool) {
-        let b = self.pop();
-        let a = self.pop();
-        loop {
-            self.go(a.clone());
-            if self.pop().falsey() == which {
-                break;
-            }
-            self.go(b.clone());
-        }
-    }
-
-    fn zip(&mut self) {
-        let a = self.pop().unwrap_arr();
-        let mut r = vec![];
-        let blank = a.first().map_or(Gval::Arr(vec![]), |x| x.factory());
-        for row in a {
-            for (y, elem) in row.as_arr().into_iter().enumerate() {
-                while r.len() < y + 1 {
-                    r.push(blank.clone())
+        match mismatch {
+            // output cut short by an error isn't a result to keep
+            Some(_) if judging.bless && outcome.error.is_none() => {
+                if let Err(e) = case.bless(outcome.output) {
+                    eprintln!("error: can't write {}: {}", case.expected_path.display(), e);
+                    std::process::exit(1);
                 }
-                r[y].push(elem.clone());
+                passed += 1;
+                println!("BLESS {}", case.name);
             }
-        }
-        self.push(Gval::Arr(r))
-    }
-
-    fn base(&mut self) {
-        let b = self.pop().unwrap_int();
-        match self.pop() {
-            Gval::Int(n) => {
-                let mut digits = vec![];
-                let mut i = n.abs();
-                while !i.is_zero() {
-                    let (j, k) = i.div_mod_floor(&b);
-                    i = j;
-                    digits.push(Gval::Int(k));
+            None => {
+                passed += 1;
+                if judging.verbose {
+                    println!("PASS {} ({:.2?})", case.name, outcome.elapsed);
                 }
-                digits.reverse();
-                self.push(Gval::Arr(digits))
             }
-            n => {
-                let mut total = BigInt::zero();
-                for digit in n.as_arr() {
-                    total = total * b.clone() + digit.unwrap_int();
+            Some(diff) => {
+                println!("FAIL {} ({:.2?})", case.name, outcome.elapsed);
+                if let Some(e) = outcome.error.as_ref().filter(|_| !outcome.timed_out()) {
+                    println!("  stopped: {}", e);
                 }
-                self.push(Gval::Int(total))
-            }
-        }
-    }
-
-    fn fold<T: Into<Gval>>(&mut self, code: Vec<u8>, vs: Vec<T>) {
-        for (i, v) in vs.into_iter().enumerate() {
-            self.push(v.into());
-            if i >= 1 {
-                self.run(&code);
-            }
-        }
-    }
-
-    fn each<T: Into<Gval>>(&mut self, code: Vec<u8>, vs: Vec<T>) {
-        for v in vs {
-            self.push(v.into());
-            self.run(&code);
-        }
-    }
-
-    fn gs_map<T: Into<Gval>>(&mut self, code: Vec<u8>, vs: Vec<T>) -> Vec<Gval> {
-        let mut r: Vec<Gval> = vec![];
-        for v in vs {
-            let lb = self.stack.len();
-            self.push(v.into());
-            self.run(&code);
-            r.extend(self.stack.drain(lb..));
-        }
-        r
-    }
-
-    fn select<T: Clone + Into<Gval>>(&mut self, code: Vec<u8>, vs: Vec<T>) -> Vec<T> {
-        let mut r: Vec<T> = vec![];
-        for v in vs {
-            self.push(v.clone().into());
-            self.run(&code);
-            if self.pop().truthy() {
-                r.push(v)
-            }
-        }
-        r
-    }
-
-    fn find<T: Clone + Into<Gval>>(&mut self, code: Vec<u8>, vs: Vec<T>) {
-        for v in vs {
-            self.push(v.clone().into());
-            self.run(&code);
-            if self.pop().truthy() {
-                self.push(v.into());
-                break;
-            }
-        }
-    }
-
-    fn go(&mut self, val: Gval) {
-        match val {
-            Gval::Blk(s) => self.run(&s),
-            _ => self.push(val),
-        }
-    }
-
-    fn run_token(&mut self, token: Gtoken) {
-        if let Some(v) = self.vars.get(token.lexeme()).cloned() {
-            self.go(v);
-            return;
-        }
-        match token {
-            Gtoken::IntLiteral(bs) => {
-                let n = BigInt::parse_bytes(bs, 10).unwrap();
-                self.push(Gval::Int(n));
-            }
-            Gtoken::SingleQuotedString(bs) => self.push(Gval::Str(unescape(bs, true))),
-            Gtoken::DoubleQuotedString(bs) => self.push(Gval::Str(unescape(bs, false))),
-            Gtoken::Symbol(b"~") => self.tilde(),
-            Gtoken::Symbol(b"`") => self.backtick(),
-            Gtoken::Symbol(b"!") => self.bang(),
-            Gtoken::Symbol(b"@") => self.at_sign(),
-            Gtoken::Symbol(b"$") => self.dollar(),
-            Gtoken::Symbol(b"+") => self.plus(),
-            Gtoken::Symbol(b"-") => self.minus(),
-            Gtoken::Symbol(b"*") => self.asterisk(),
-            Gtoken::Symbol(b"/") => self.slash(),
-            Gtoken::Symbol(b"%") => self.percent(),
-            Gtoken::Symbol(b"|") => self.vertical_bar(),
-            Gtoken::Symbol(b"&") => self.ampersand(),
-            Gtoken::Symbol(b"^") => self.caret(),
-            Gtoken::Symbol(b"[") => self.lb.push(self.stack.len()),
-            Gtoken::Symbol(b"]") => {
-                let vs = self.stack.drain(self.lb.pop().unwrap_or(0)..).collect();
-                self.push(Gval::Arr(vs));
-            }
-            Gtoken::Symbol(b"\\") => {
-                let b = self.pop();
-                let a = self.pop();
-                self.push(b);
-                self.push(a);
-            }
-            Gtoken::Symbol(b";") => {
-                let _ = self.pop();
-            }
-            Gtoken::Symbol(b"<") => self.lteqgt(Ordering::Less),
-            Gtoken::Symbol(b"=") => self.lteqgt(Ordering::Equal),
-            Gtoken::Symbol(b">") => self.lteqgt(Ordering::Greater),
-            Gtoken::Symbol(b",") => self.comma(),
-            Gtoken::Symbol(b".") => self.dup(),
-            Gtoken::Symbol(b"?") => self.question(),
-            Gtoken::Symbol(b"(") => self.left_paren(),
-            Gtoken::Symbol(b")") => self.right_paren(),
-            Gtoken::Symbol(b"and") => {
-                let b = self.pop();
-                let a = self.pop();
-                self.go(if a.truthy() { b } else { a });
-            }
-            Gtoken::Symbol(b"or") => {
-                let b = self.pop();
-                let a = self.pop();
-                self.go(if a.falsey() { b } else { a });
-            }
-            Gtoken::Symbol(b"xor") => {
-                let b = self.pop();
-                let a = self.pop();
-                self.push(Gval::bool(a.truthy() ^ b.truthy()));
-            }
-            Gtoken::Symbol(b"n") => self.push(Gval::Str(b"\n".to_vec())),
-            Gtoken::Symbol(b"print") => {
-                let a = self.pop();
-                print(&a.to_gs());
-            }
-            Gtoken::Symbol(b"p") => {
-                let a = self.pop();
-                print(&a.inspect());
-                print(b"\n");
-            }
-            Gtoken::Symbol(b"puts") => {
-                let a = self.pop();
-                print(&a.to_gs());
-                print(b"\n");
-            }
-            Gtoken::Symbol(b"rand") => self.rand(),
-            Gtoken::Symbol(b"do") => self.do_loop(),
-            Gtoken::Symbol(b"while") => self.while_loop(true),
-            Gtoken::Symbol(b"until") => self.while_loop(false),
-            Gtoken::Symbol(b"if") => {
-                let c = self.pop();
-                let b = self.pop();
-                let a = self.pop();
-                if a.truthy() {
-                    self.go(b);
-                } else {
-                    self.go(c);
+                println!("  {}", diff.replace('\n', "\n  "));
+                if judging.bless {
+                    println!("  not blessed, as it stopped early");
                 }
             }
-            Gtoken::Symbol(b"abs") => {
-                let a = self.pop();
-                self.push(Gval::Int(a.unwrap_int().abs()));
-            }
-            Gtoken::Symbol(b"zip") => self.zip(),
-            Gtoken::Symbol(b"base") => self.base(),
-            Gtoken::Block(_, src) => self.push(Gval::Blk(src.to_owned())),
-            Gtoken::Symbol(_) => {}
-            Gtoken::Comment(_) => {}
         }
     }
-}
-
-#[derive(clap::Parser, Debug)]
-struct Cli {
-    #[clap(long)]
-    code_path: Option<String>,
-    #[clap(short = 'e', long, allow_hyphen_values = true)]
-    code: Option<String>,
-    #[clap(long)]
-    input_path: Option<String>,
-    #[clap(short = 'i', long, allow_hyphen_values = true)]
-    input: Option<String>,
-    #[clap(short = 'q', long, takes_value = false)]
-    no_implicit_output: bool,
-    #[clap(short = 's', long, takes_value = false)]
-    input_from_stdin: bool,
-    #[clap(long, takes_value = false)]
-    args: bool,
-    args_vec: Vec<String>,
+    let percent = 100.0 * passed as f64 / cases.len().max(1) as f64;
+    println!("passed {}/{} ({:.1}%)", passed, cases.len(), percent);
+    if passed < cases.len() {
+        std::process::exit(1);
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
-    let mut gs = Gs::new();
+    match &cli.command {
+        Some(Command::Fmt { path, write }) => return rewrite(path, *write, format),
+        Some(Command::Minify { path, write }) => return rewrite(path, *write, minify),
+        Some(Command::Analyze { path }) => return analyze(path),
+        Some(Command::Lint { path }) => return lint(path),
+        Some(Command::Test {
+            dir,
+            compare,
+            bless,
+            max_time_ms,
+            max_output,
+            verbose,
+        }) => {
+            let limits = Limits {
+                time: Some(std::time::Duration::from_millis(*max_time_ms)),
+                output: Some(*max_output),
+                ..Limits::none()
+            };
+            let judging = Judging {
+                limits,
+                compare: *compare,
+                bless: *bless,
+                verbose: *verbose,
+            };
+            return test(dir, judging);
+        }
+        None => {}
+    }
+    let mut gs = Gs::with_config(config(&cli));
+    if cli.repl {
+        repl::repl(gs);
+        return;
+    }
     let input = if cli.args {
-        Gval::Arr(
+        Gval::from(
             cli.args_vec
                 .iter()
-                .map(|x| Gval::Str(x.as_bytes().to_vec()))
-                .collect(),
+                .map(|x| Gval::from(x.as_str()))
+                .collect::<Vec<_>>(),
         )
     } else if cli.input_from_stdin {
         let mut bytes = vec![];
         std::io::stdin().read_to_end(&mut bytes).unwrap();
-        Gval::Str(bytes)
-    } else if let Some(path) = cli.input_path {
-        Gval::Str(std::fs::read(path).unwrap())
-    } else if let Some(string) = cli.input {
-        Gval::Str(string.as_bytes().to_vec())
+        Gval::from(bytes)
+    } else if let Some(path) = &cli.input_path {
+        Gval::from(std::fs::read(path).unwrap())
+    } else if let Some(string) = &cli.input {
+        Gval::from(string.as_str())
     } else {
-        Gval::Str(vec![])
+        Gval::from("")
     };
-    let code = if let Some(path) = cli.code_path {
+    let code = if let Some(path) = &cli.code_path {
         std::fs::read(path).unwrap()
-    } else if let Some(code) = cli.code {
+    } else if let Some(code) = &cli.code {
         code.as_bytes().to_vec()
     } else {
         eprintln!(
@@ -786,10 +361,61 @@ fn main() {
         );
         std::process::exit(1)
     };
-    gs.stack.push(input);
-    gs.run(&code);
-    if !cli.no_implicit_output {
-        gs.stack = vec![Gval::Arr(gs.stack)];
-        gs.run(b"puts");
+    gs.push(input);
+    if cli.stream_stdin {
+        gs.set_input(std::io::stdin().lock());
+    }
+    gs.set_sink(Sink::stdout());
+    let mut profiler = Profiler::new();
+    let profiling = cli.profile || cli.profile_json.is_some();
+    let mut coverage = Coverage::new(code.clone());
+    let covering = cli.coverage || cli.coverage_lcov.is_some();
+    let mut result = if profiling {
+        gs.run_with(&code, &mut profiler)
+    } else if covering {
+        gs.run_with(&code, &mut coverage)
+    } else if cli.trace || cli.trace_json {
+        let mut tracer = Tracer {
+            out: std::io::BufWriter::new(std::io::stderr()),
+            width: cli.trace_width,
+            json: cli.trace_json,
+            source: Some(code.clone()),
+        };
+        gs.run_with(&code, &mut tracer)
+    } else {
+        gs.run(&code)
+    };
+    if result.is_ok() && !cli.no_implicit_output {
+        result = gs.output_stack();
+    }
+    gs.flush().unwrap();
+    if let Some(path) = &cli.profile_json {
+        if let Err(e) = std::fs::write(path, profiler.json(&code)) {
+            eprintln!("error: can't write the profile to {}: {}", path, e);
+        }
+    } else if profiling {
+        eprint!("{}", profiler.report(&code));
+    }
+    if covering {
+        for path in &cli.coverage_input {
+            let mut gs = Gs::with_config(config(&cli));
+            gs.push(Gval::from(std::fs::read(path).unwrap()));
+            let _ = gs.run_with(&code, &mut coverage);
+        }
+        let source = cli.code_path.as_deref().unwrap_or("-");
+        if let Some(path) = &cli.coverage_lcov {
+            if let Err(e) = std::fs::write(path, coverage.lcov(source)) {
+                eprintln!("error: can't write the coverage to {}: {}", path, e);
+            }
+        } else {
+            eprint!("{}", coverage.listing());
+        }
+    }
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        if let Some(excerpt) = e.excerpt(&code) {
+            eprintln!("{}", excerpt);
+        }
+        std::process::exit(1);
     }
 }
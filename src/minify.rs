@@ -0,0 +1,273 @@
+use crate::code::Builtin;
+use crate::error::{ErrorKind, GsError};
+use crate::format::{assigned_whitespace, assigns_hiders, hidden, is_whitespace, parse, strings};
+use crate::parse::{parse_code, Gtoken, Token};
+use std::collections::{HashMap, HashSet};
+
+/// Shrinks `source` for submission: drops comments and any whitespace that
+/// doesn't keep two tokens apart, and renames variables with longer names
+/// to single letters that aren't already used or builtins.
+///
+/// A block's source is its value, which `p` prints and `if` tests for
+/// emptiness, so blocks are kept as written unless nothing can see them:
+/// those run at once with `~`, or assigned and dropped with `:name;`, which
+/// only ever run. Only those shrink, and only names that appear nowhere else
+/// are renamed. Names that a string literal mentions are neither renamed nor
+/// given out either, in case the string is run with `~`. The result is
+/// checked to tokenize to the same tokens, renamed, and the same blocks.
+///
+/// ```
+/// let code = b"# double each\n~]{2 *}%:doubled;\n{doubled p}:show;\nshow";
+/// assert_eq!(golfscript_rs::minify(code)?, b"~]{2 *}%:a;{a p}:b;b");
+/// # Ok::<(), golfscript_rs::GsError>(())
+/// ```
+pub fn minify(source: &[u8]) -> Result<Vec<u8>, GsError> {
+    let tokens = parse(source)?;
+    if let Some(span) = assigned_whitespace(&tokens) {
+        return Err(GsError {
+            kind: ErrorKind::Parse {
+                message: "`:` assigns whitespace, so the spacing can't change".to_owned(),
+            },
+            span: Some(span),
+        });
+    }
+    let hiding = !assigns_hiders(&tokens);
+    let names = renames(&tokens, hiding);
+    let mut out = vec![];
+    emit(&tokens, &names, hiding, &mut out);
+    let before = outline(&tokens, &names, hiding);
+    let after = parse(&out).map(|tokens| outline(&tokens, &HashMap::new(), hiding));
+    if after.ok().is_none_or(|after| after != before) {
+        return Err(GsError::new(ErrorKind::Parse {
+            message: "rewriting changed the tokens".to_owned(),
+        }));
+    }
+    Ok(out)
+}
+
+/// The single letter to give each variable that gets one.
+fn renames(tokens: &[Token], hiding: bool) -> HashMap<Vec<u8>, u8> {
+    let mut used = HashMap::new();
+    let mut assigned = vec![];
+    names(tokens, &mut used, &mut assigned);
+    let mut quoted = HashMap::new();
+    for string in strings(tokens) {
+        if let Ok((_, tokens)) = parse_code(string) {
+            names(&tokens, &mut quoted, &mut vec![]);
+        }
+    }
+    let mut seen = HashSet::new();
+    as_written(tokens, hiding, &mut seen);
+    let mut candidates: Vec<&[u8]> = assigned
+        .into_iter()
+        .filter(|name| name.len() > 1 && is_identifier(name))
+        .filter(|name| Builtin::from_name(name).is_none() && !quoted.contains_key(name))
+        .filter(|name| !seen.contains(name))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates.sort_by_key(|name| std::cmp::Reverse(used[name]));
+    let mut free = (b'a'..=b'z')
+        .chain(b'A'..=b'Z')
+        .chain([b'_'])
+        .map(|c| [c])
+        .filter(|c| Builtin::from_name(c).is_none())
+        .filter(|c| !used.contains_key(&c[..]) && !quoted.contains_key(&c[..]));
+    let mut renames = HashMap::new();
+    for name in candidates {
+        let Some(letter) = free.next() else { break };
+        renames.insert(name.to_vec(), letter[0]);
+    }
+    renames
+}
+
+/// Counts the uses of each identifier, and lists those that are assigned.
+fn names<'a>(
+    tokens: &[Token<'a>],
+    used: &mut HashMap<&'a [u8], usize>,
+    assigned: &mut Vec<&'a [u8]>,
+) {
+    let mut tokens = tokens.iter().peekable();
+    while let Some(token) = tokens.next() {
+        match &token.gtoken {
+            Gtoken::Symbol(b":") => {
+                if let Some(Token {
+                    gtoken: Gtoken::Symbol(name),
+                    ..
+                }) = tokens.peek()
+                {
+                    assigned.push(name);
+                }
+            }
+            Gtoken::Symbol(name) if is_identifier(name) => *used.entry(name).or_default() += 1,
+            Gtoken::Block(inner, _) => self::names(inner, used, assigned),
+            _ => {}
+        }
+    }
+}
+
+/// Collects the names in the blocks that are kept as written, which can't
+/// be renamed.
+fn as_written<'a>(tokens: &[Token<'a>], hiding: bool, seen: &mut HashSet<&'a [u8]>) {
+    for (i, token) in tokens.iter().enumerate() {
+        if let Gtoken::Block(inner, _) = &token.gtoken {
+            if hiding && hidden(tokens, i) {
+                as_written(inner, hiding, seen);
+            } else {
+                let mut used = HashMap::new();
+                names(inner, &mut used, &mut vec![]);
+                seen.extend(used.into_keys());
+            }
+        }
+    }
+}
+
+fn is_identifier(name: &[u8]) -> bool {
+    name.first()
+        .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_')
+}
+
+/// Writes `tokens` without comments or whitespace, renamed, spacing them
+/// only where they would run together.
+fn emit(tokens: &[Token], names: &HashMap<Vec<u8>, u8>, hiding: bool, out: &mut Vec<u8>) {
+    let mut last: Vec<u8> = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        let word = match &token.gtoken {
+            Gtoken::Comment(_) => continue,
+            Gtoken::Symbol(s) if is_whitespace(s) => continue,
+            Gtoken::Block(inner, _) if hiding && hidden(tokens, i) => {
+                let mut block = b"{".to_vec();
+                emit(inner, names, hiding, &mut block);
+                block.push(b'}');
+                block
+            }
+            Gtoken::Block(_, src) => [&b"{"[..], src, &b"}"[..]].concat(),
+            Gtoken::Symbol(name) => names.get(*name).map_or(name.to_vec(), |&n| vec![n]),
+            gtoken => gtoken.lexeme().to_vec(),
+        };
+        if !last.is_empty() && !apart(&last, &word) {
+            out.push(b' ');
+        }
+        out.extend_from_slice(&word);
+        last = word;
+    }
+}
+
+/// The tokens that mean something, renamed, with the blocks that [`emit`]
+/// shrinks opened up and the others as written.
+fn outline(tokens: &[Token], names: &HashMap<Vec<u8>, u8>, hiding: bool) -> Vec<Vec<u8>> {
+    let mut outline = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.gtoken {
+            Gtoken::Comment(_) => {}
+            Gtoken::Symbol(s) if is_whitespace(s) => {}
+            Gtoken::Block(inner, _) if hiding && hidden(tokens, i) => {
+                outline.push(b"{".to_vec());
+                outline.extend(self::outline(inner, names, hiding));
+                outline.push(b"}".to_vec());
+            }
+            Gtoken::Block(_, src) => outline.push([&b"{"[..], src, &b"}"[..]].concat()),
+            Gtoken::Symbol(name) => {
+                outline.push(names.get(*name).map_or(name.to_vec(), |&n| vec![n]))
+            }
+            gtoken => outline.push(gtoken.lexeme().to_vec()),
+        }
+    }
+    outline
+}
+
+/// Whether `a` and `b` written together still tokenize as `a` then `b`.
+fn apart(a: &[u8], b: &[u8]) -> bool {
+    let joined = [a, b].concat();
+    match parse_code(&joined) {
+        Ok((rest, tokens)) => {
+            rest.is_empty()
+                && tokens.len() == 2
+                && tokens[0].span.end == a.len()
+                && tokens[1].span.end == joined.len()
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golfscript_bytes;
+
+    /// Checks that `code` minifies to `minified`, and prints the same on
+    /// `input` before and after.
+    fn minifies(code: &[u8], input: &[u8], minified: &[u8]) {
+        let out = minify(code).unwrap();
+        assert_eq!(
+            out.escape_ascii().to_string(),
+            minified.escape_ascii().to_string()
+        );
+        assert_eq!(
+            golfscript_bytes(input, &out),
+            golfscript_bytes(input, code),
+            "{} runs differently",
+            code.escape_ascii()
+        );
+    }
+
+    #[test]
+    fn keeps_blocks_that_can_be_seen() {
+        minifies(b"{ }{1}{2}if", b"", b"{ }{1}{2}if");
+        minifies(b"{1 +}p", b"", b"{1 +}p");
+        minifies(b"{1 +}`", b"", b"{1 +}`");
+        minifies(b"{ 1 }'x'+", b"", b"{ 1 }'x'+");
+        minifies(b"[1 2 3] { 2 * } %", b"", b"[1 2 3]{ 2 * }%");
+    }
+
+    #[test]
+    fn shrinks_blocks_that_only_run() {
+        minifies(b"{ 1 2 + }~", b"", b"{1 2+}~");
+        minifies(b"{ . * }:square; 3 square", b"", b"{.*}:a;3a");
+        minifies(b"{ { 1 } ~ }~", b"", b"{{1}~}~");
+        // `:f` without `;` leaves the block on the stack, to be printed
+        minifies(b"{ 1 }:f", b"", b"{ 1 }:f");
+    }
+
+    #[test]
+    fn renames_only_where_unseen() {
+        minifies(b"5:count; {count p}:show; show", b"", b"5:a;{a p}:b;b");
+        // `count` is part of a block's value, so it keeps its name
+        minifies(b"5:count; {count}p", b"", b"5:count;{count}p");
+        // and so is anything `"..."~` may mention
+        minifies(b"5:count; \"count\"~p", b"", b"5:count;\"count\"~p");
+    }
+
+    #[test]
+    fn keeps_blocks_when_hiding_is_redefined() {
+        minifies(b"{;}:~; { 1 }~p", b"", b"{;}:~;{ 1 }~p");
+    }
+
+    #[test]
+    fn runs_classics_the_same() {
+        let programs: [(&[u8], &[u8]); 6] = [
+            (b"~]{+}*", b"1 2 3 4"),
+            (b"~{.@\\%.}do;", b"140 150"),
+            (b"# sums\n~ ] 0 \\ { + } / :total ; total p", b"1 2 3"),
+            (b"10 , { . * } % p", b""),
+            (b"~:limit;0 1{.@+.limit<}do;]p", b"100"),
+            (b"'abc' { 1 + } % n", b""),
+        ];
+        for (code, input) in programs {
+            let out = minify(code).unwrap();
+            assert!(out.len() <= code.len());
+            assert_eq!(
+                golfscript_bytes(input, &out),
+                golfscript_bytes(input, code),
+                "{} minified to {}",
+                code.escape_ascii(),
+                out.escape_ascii()
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_assigned_whitespace() {
+        assert!(minify(b"{1}: ;2 3").is_err());
+    }
+}
@@ -7,6 +7,39 @@ use nom::multi::many0;
 use nom::sequence::{delimited, pair};
 use nom::IResult;
 
+/// Where a token sits in the source: a byte range plus the 1-based line and
+/// column (in bytes) of its first byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /// An empty span at `offset` in `source`.
+    pub fn point(source: &[u8], offset: usize) -> Span {
+        let before = &source[..offset];
+        let line_start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        Span {
+            start: offset,
+            end: offset,
+            line: 1 + before.iter().filter(|&&b| b == b'\n').count(),
+            col: 1 + offset - line_start,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Token<'a> {
+    pub gtoken: Gtoken<'a>,
+    pub span: Span,
+}
+
 #[derive(Clone, Debug)]
 pub enum Gtoken<'a> {
     Symbol(&'a [u8]),             // [a-zA-Z_][a-zA-Z0-9_]* or final .
@@ -14,7 +47,7 @@ pub enum Gtoken<'a> {
     DoubleQuotedString(&'a [u8]), // "(?:\\.|[^"])*"?
     IntLiteral(&'a [u8]),         // -?[0-9]+
     Comment(&'a [u8]),            // #[^\n\r]*
-    Block(Vec<Gtoken<'a>>, &'a [u8]),
+    Block(Vec<Token<'a>>, &'a [u8]),
 }
 
 impl<'a> Gtoken<'a> {
@@ -36,7 +69,7 @@ fn single<'a, Error: nom::error::ParseError<&'a [u8]>>(
     take_while_m_n(1, 1, move |c| c == b)
 }
 
-fn parse_identifier(i: &[u8]) -> IResult<&[u8], Gtoken> {
+fn parse_identifier(i: &[u8]) -> IResult<&[u8], Gtoken<'_>> {
     let head = take_while_m_n(1, 1, |c| is_alphabetic(c) || c == b'_');
     let tail = take_while(|c| is_alphabetic(c) || is_digit(c) || c == b'_');
     let (i, s) = recognize(pair(head, tail))(i)?;
@@ -55,17 +88,17 @@ fn parse_string(delimiter: u8, i: &[u8]) -> IResult<&[u8], &[u8]> {
     ))(i)
 }
 
-fn parse_single_quoted_string(i: &[u8]) -> IResult<&[u8], Gtoken> {
+fn parse_single_quoted_string(i: &[u8]) -> IResult<&[u8], Gtoken<'_>> {
     let (i, s) = parse_string(b'\'', i)?;
     Ok((i, Gtoken::SingleQuotedString(s)))
 }
 
-fn parse_double_quoted_string(i: &[u8]) -> IResult<&[u8], Gtoken> {
+fn parse_double_quoted_string(i: &[u8]) -> IResult<&[u8], Gtoken<'_>> {
     let (i, s) = parse_string(b'"', i)?;
     Ok((i, Gtoken::DoubleQuotedString(s)))
 }
 
-fn parse_int_literal(i: &[u8]) -> IResult<&[u8], Gtoken> {
+fn parse_int_literal(i: &[u8]) -> IResult<&[u8], Gtoken<'_>> {
     let (i, s) = recognize(pair(
         take_while_m_n(0, 1, |b| b == b'-'),
         take_while1(is_digit),
@@ -73,35 +106,77 @@ fn parse_int_literal(i: &[u8]) -> IResult<&[u8], Gtoken> {
     Ok((i, Gtoken::IntLiteral(s)))
 }
 
-fn parse_comment(i: &[u8]) -> IResult<&[u8], Gtoken> {
+fn parse_comment(i: &[u8]) -> IResult<&[u8], Gtoken<'_>> {
     let (i, s) = recognize(pair(single(b'#'), take_while(|b| b != b'\r' && b != b'\n')))(i)?;
     Ok((i, Gtoken::Comment(s)))
 }
 
-fn parse_block(i: &[u8]) -> IResult<&[u8], Gtoken> {
+fn parse_block<'a>(base: &'a [u8], i: &'a [u8]) -> IResult<&'a [u8], Gtoken<'a>> {
     let (i, _) = single(b'{')(i)?;
-    let (i, (src, tokens)) = consumed(parse_code)(i)?;
+    let (i, (src, tokens)) = consumed(many0(|i| parse_token(base, i)))(i)?;
     let (i, _) = single(b'}')(i)?;
     Ok((i, Gtoken::Block(tokens, src)))
 }
 
-fn parse_symbol(i: &[u8]) -> IResult<&[u8], Gtoken> {
+fn parse_symbol(i: &[u8]) -> IResult<&[u8], Gtoken<'_>> {
     let (i, s) = take_while_m_n(1, 1, |b| b != b'{' && b != b'}' && b != b'"' && b != b'\'')(i)?;
     Ok((i, Gtoken::Symbol(s)))
 }
 
-pub fn parse_token(i: &[u8]) -> IResult<&[u8], Gtoken> {
-    alt((
+/// Parses one token of `base`, starting at its suffix `i`.
+fn parse_token<'a>(base: &'a [u8], i: &'a [u8]) -> IResult<&'a [u8], Token<'a>> {
+    let (rest, gtoken) = alt((
         parse_identifier,
         parse_single_quoted_string,
         parse_double_quoted_string,
         parse_int_literal,
         parse_comment,
-        parse_block,
+        |i| parse_block(base, i),
         parse_symbol,
-    ))(i)
+    ))(i)?;
+    let span = Span {
+        start: base.len() - i.len(),
+        end: base.len() - rest.len(),
+        line: 0,
+        col: 0,
+    };
+    Ok((rest, Token { gtoken, span }))
 }
 
-pub fn parse_code(i: &[u8]) -> IResult<&[u8], Vec<Gtoken>> {
-    many0(parse_token)(i)
+/// Fills in the line and column of `tokens`, which are in source order, given
+/// those of `cursor`; `cursor` ends up at the start of the last token.
+fn locate(source: &[u8], tokens: &mut [Token], cursor: &mut Span) {
+    for token in tokens {
+        for &b in &source[cursor.start..token.span.start] {
+            if b == b'\n' {
+                cursor.line += 1;
+                cursor.col = 1;
+            } else {
+                cursor.col += 1;
+            }
+        }
+        cursor.start = token.span.start;
+        token.span.line = cursor.line;
+        token.span.col = cursor.col;
+        if let Gtoken::Block(inner, _) = &mut token.gtoken {
+            locate(source, inner, cursor);
+        }
+    }
+}
+
+/// Tokenizes as much of `i` as possible. Every token, including those inside
+/// blocks, is spanned relative to `i`.
+pub fn parse_code(i: &[u8]) -> IResult<&[u8], Vec<Token<'_>>> {
+    let (rest, mut tokens) = many0(|j| parse_token(i, j))(i)?;
+    locate(i, &mut tokens, &mut Span::point(i, 0));
+    Ok((rest, tokens))
+}
+
+/// Whether `src` stops inside an unclosed block or string, so that more
+/// lines could complete it.
+pub fn is_incomplete(src: &[u8]) -> bool {
+    match parse_code(src) {
+        Ok((rest, _)) => matches!(rest.first(), Some(b'{' | b'"' | b'\'')),
+        Err(_) => false,
+    }
 }
@@ -0,0 +1,240 @@
+use crate::gs::{Gs, Hook, Location, TokenKind};
+use crate::parse::Span;
+use crate::util::json_string;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// What a [`Profile`] measures.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Profiled {
+    /// A builtin, by name.
+    Builtin(Vec<u8>),
+    /// A variable run as code, by name.
+    Var(Vec<u8>),
+    /// A block literal of the source, however it was called.
+    Block(Span),
+}
+
+/// How often something ran, and for how long in all, including the code it
+/// called.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub what: Profiled,
+    pub calls: u64,
+    pub time: Duration,
+}
+
+/// A [`Hook`] that counts and times the builtins, variables and blocks a
+/// program runs.
+///
+/// A token's time runs until the next token beside it starts, so it takes
+/// in any block it calls, such as the block `%` maps with. Time spent in a
+/// recursive call is counted once, for the outermost call.
+#[derive(Default)]
+pub struct Profiler {
+    profiles: Vec<Profile>,
+    /// How many calls of each profile are open, to spot recursion.
+    active: Vec<usize>,
+    builtins: HashMap<Vec<u8>, usize>,
+    vars: HashMap<Vec<u8>, usize>,
+    blocks: HashMap<Span, usize>,
+    /// What is running, innermost last.
+    open: Vec<Open>,
+    start: Option<Instant>,
+    elapsed: Duration,
+}
+
+struct Open {
+    profile: usize,
+    start: Instant,
+    depth: usize,
+    block: bool,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// The index of the profile of `what`, added if it's new.
+    fn profile(&mut self, what: Profiled) -> usize {
+        let profiles = &mut self.profiles;
+        let mut add = |what: Profiled| {
+            profiles.push(Profile {
+                what,
+                calls: 0,
+                time: Duration::ZERO,
+            });
+            self.active.push(0);
+            profiles.len() - 1
+        };
+        match what {
+            Profiled::Builtin(name) => *self
+                .builtins
+                .entry(name.clone())
+                .or_insert_with(|| add(Profiled::Builtin(name))),
+            Profiled::Var(name) => *self
+                .vars
+                .entry(name.clone())
+                .or_insert_with(|| add(Profiled::Var(name))),
+            Profiled::Block(span) => *self
+                .blocks
+                .entry(span)
+                .or_insert_with(|| add(Profiled::Block(span))),
+        }
+    }
+
+    fn open(&mut self, profile: usize, depth: usize, block: bool) {
+        self.profiles[profile].calls += 1;
+        self.active[profile] += 1;
+        let start = Instant::now();
+        self.start.get_or_insert(start);
+        self.open.push(Open {
+            profile,
+            start,
+            depth,
+            block,
+        });
+    }
+
+    /// Ends what is running at `depth` or deeper. Blocks at `depth` end only
+    /// when they return.
+    fn close(&mut self, depth: usize, returned: bool) {
+        let now = Instant::now();
+        while let Some(open) = self.open.last() {
+            if open.depth < depth || open.depth == depth && open.block && !returned {
+                break;
+            }
+            let open = self.open.pop().unwrap();
+            self.active[open.profile] -= 1;
+            if self.active[open.profile] == 0 {
+                self.profiles[open.profile].time += now - open.start;
+            }
+        }
+        if let Some(start) = self.start {
+            self.elapsed = now - start;
+        }
+    }
+
+    /// Everything measured, the longest running first. Anything still
+    /// running, because the run stopped with an error, ends now.
+    pub fn profiles(&mut self) -> Vec<Profile> {
+        self.close(0, true);
+        let mut profiles = self.profiles.clone();
+        profiles.sort_by(|a, b| b.time.cmp(&a.time).then(b.calls.cmp(&a.calls)));
+        profiles
+    }
+
+    /// The time from the first token to the last.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// A table of [`Profiler::profiles`], showing blocks as written in
+    /// `source`.
+    pub fn report(&mut self, source: &[u8]) -> String {
+        let profiles = self.profiles();
+        let total = self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+        let mut report = format!("{:>10} {:>12} {:>6}  what\n", "calls", "time (ms)", "%");
+        for p in &profiles {
+            let what = match &p.what {
+                Profiled::Builtin(name) => String::from_utf8_lossy(name).into_owned(),
+                Profiled::Var(name) => format!("var {}", String::from_utf8_lossy(name)),
+                Profiled::Block(span) => format!(
+                    "block @{}:{} {}",
+                    span.line,
+                    span.col,
+                    String::from_utf8_lossy(&excerpt(source, *span))
+                ),
+            };
+            let _ = writeln!(
+                report,
+                "{:>10} {:>12.3} {:>6.1}  {}",
+                p.calls,
+                p.time.as_secs_f64() * 1e3,
+                p.time.as_secs_f64() / total * 100.0,
+                what
+            );
+        }
+        let _ = writeln!(report, "total {:.3} ms", total * 1e3);
+        report
+    }
+
+    /// [`Profiler::profiles`] as a JSON object, with times in nanoseconds.
+    pub fn json(&mut self, source: &[u8]) -> String {
+        let profiles = self.profiles();
+        let entries: Vec<String> = profiles
+            .iter()
+            .map(|p| {
+                let (kind, name, span) = match &p.what {
+                    Profiled::Builtin(name) => ("builtin", json_string(name), "null".to_owned()),
+                    Profiled::Var(name) => ("var", json_string(name), "null".to_owned()),
+                    Profiled::Block(s) => (
+                        "block",
+                        json_string(&source[s.start.min(source.len())..s.end.min(source.len())]),
+                        format!(
+                            "{{\"start\":{},\"end\":{},\"line\":{},\"col\":{}}}",
+                            s.start, s.end, s.line, s.col
+                        ),
+                    ),
+                };
+                format!(
+                    "{{\"kind\":\"{}\",\"name\":{},\"span\":{},\"calls\":{},\"nanos\":{}}}",
+                    kind,
+                    name,
+                    span,
+                    p.calls,
+                    p.time.as_nanos()
+                )
+            })
+            .collect();
+        format!(
+            "{{\"nanos\":{},\"profiles\":[{}]}}\n",
+            self.elapsed.as_nanos(),
+            entries.join(",")
+        )
+    }
+}
+
+/// The start of the source of `span`, on one line.
+fn excerpt(source: &[u8], span: Span) -> Vec<u8> {
+    let text = source.get(span.start..span.end).unwrap_or_default();
+    let mut text: Vec<u8> = text
+        .iter()
+        .map(|&b| if b.is_ascii_whitespace() { b' ' } else { b })
+        .collect();
+    if text.len() > 40 {
+        text.truncate(37);
+        text.extend_from_slice(b"...");
+    }
+    text
+}
+
+impl Hook for Profiler {
+    fn before(&mut self, _gs: &Gs, token: &Location) {
+        self.close(token.depth, false);
+        let known = match token.kind {
+            TokenKind::Builtin => self.builtins.get(token.lexeme),
+            TokenKind::Var => self.vars.get(token.lexeme),
+            _ => return,
+        };
+        let profile = match (known, token.kind) {
+            (Some(&profile), _) => profile,
+            (None, TokenKind::Builtin) => self.profile(Profiled::Builtin(token.lexeme.to_vec())),
+            (None, _) => self.profile(Profiled::Var(token.lexeme.to_vec())),
+        };
+        self.open(profile, token.depth, false);
+    }
+
+    fn enter(&mut self, _gs: &Gs, block: Option<Span>, depth: usize) {
+        if let Some(span) = block {
+            let profile = self.profile(Profiled::Block(span));
+            self.open(profile, depth, true);
+        }
+    }
+
+    fn exit(&mut self, _gs: &Gs, depth: usize) {
+        self.close(depth, true);
+    }
+}
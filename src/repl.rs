@@ -0,0 +1,127 @@
+use golfscript_rs::{is_incomplete, Gs, Gval, Sink};
+use std::cell::Cell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+const HELP: &str = "\
+Enter GolfScript to run it; the stack is shown after each entry. Lines
+continue while a block or string is open. Commands:
+    #clear      empty the stack
+    #vars       list the variables
+    #rand       restart `rand` from its initial seed
+    #history    list previous entries
+    #N          run entry N again
+    #help       show this
+    #quit       leave (so does end of input)";
+
+/// Reads entries from stdin and runs them on `gs` until end of input.
+pub fn repl(mut gs: Gs) {
+    // track whether output ended a line, so the stack starts on a fresh one
+    let at_line_start = Rc::new(Cell::new(true));
+    let tracker = at_line_start.clone();
+    gs.set_sink(Sink::callback(move |bytes| {
+        let _ = io::stdout().write_all(bytes);
+        if let Some(&last) = bytes.last() {
+            tracker.set(last == b'\n');
+        }
+    }));
+    let mut history: Vec<Vec<u8>> = vec![];
+    let mut lines = io::stdin().lock().split(b'\n');
+    println!(
+        "golfscript-rs {}; #help for commands",
+        env!("CARGO_PKG_VERSION")
+    );
+    while let Some(entry) = read_entry(&mut lines) {
+        let code = match entry.trim_ascii() {
+            b"" => continue,
+            b"#help" => {
+                println!("{}", HELP);
+                continue;
+            }
+            b"#clear" => {
+                gs.take_stack();
+                show_stack(&gs);
+                continue;
+            }
+            b"#vars" => {
+                for (name, val) in gs.vars() {
+                    let val = val.clone().inspect();
+                    println!(
+                        "{} = {}",
+                        String::from_utf8_lossy(name),
+                        String::from_utf8_lossy(&val)
+                    );
+                }
+                continue;
+            }
+            b"#rand" => {
+                gs.reset_rng();
+                continue;
+            }
+            b"#history" => {
+                for (i, code) in history.iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, String::from_utf8_lossy(code));
+                }
+                continue;
+            }
+            b"#quit" => break,
+            [b'#', n @ ..] if !n.is_empty() && n.iter().all(u8::is_ascii_digit) => {
+                let n: usize = std::str::from_utf8(n).unwrap().parse().unwrap_or(0);
+                match history.get(n.wrapping_sub(1)) {
+                    Some(code) => {
+                        println!("{}", String::from_utf8_lossy(code));
+                        code.clone()
+                    }
+                    None => {
+                        eprintln!("no entry {}", n);
+                        continue;
+                    }
+                }
+            }
+            _ => entry,
+        };
+        at_line_start.set(true);
+        let result = gs.run(&code);
+        if !at_line_start.get() {
+            println!();
+        }
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+            if let Some(excerpt) = e.excerpt(&code) {
+                eprintln!("{}", excerpt);
+            }
+        }
+        history.push(code);
+        show_stack(&gs);
+    }
+}
+
+/// Reads one entry, prompting for more lines while it has an unclosed block
+/// or string. `None` at end of input.
+fn read_entry(lines: &mut impl Iterator<Item = io::Result<Vec<u8>>>) -> Option<Vec<u8>> {
+    let mut entry = vec![];
+    let mut prompt = "gs> ";
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush().ok()?;
+        match lines.next() {
+            Some(Ok(line)) => entry.extend(line.strip_suffix(b"\r").unwrap_or(&line)),
+            // an unfinished entry still runs, to report what's unclosed
+            _ if !entry.is_empty() => return Some(entry),
+            _ => {
+                println!();
+                return None;
+            }
+        }
+        if !is_incomplete(&entry) {
+            return Some(entry);
+        }
+        entry.push(b'\n');
+        prompt = "..> ";
+    }
+}
+
+fn show_stack(gs: &Gs) {
+    let stack = Gval::from(gs.stack().to_vec()).inspect();
+    println!("{}", String::from_utf8_lossy(&stack));
+}
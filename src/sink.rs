@@ -0,0 +1,142 @@
+use std::io::{self, Write};
+
+/// Where an interpreter's output goes: everything `print`, `puts` and `p`
+/// write, and the final stack. Bytes are passed through exactly as the
+/// program produced them, whether or not they are UTF-8.
+///
+/// Interpreters start with [`Sink::buffer`]; choose another with
+/// [`Gs::set_sink`](crate::Gs::set_sink).
+pub struct Sink(Kind);
+
+enum Kind {
+    Buffer(Vec<u8>),
+    Bounded {
+        buf: Vec<u8>,
+        max: usize,
+        truncated: bool,
+    },
+    Writer(Box<dyn Write>),
+}
+
+/// Adapts a closure to [`Write`].
+struct Callback<F>(F);
+
+impl<F: FnMut(&[u8])> Write for Callback<F> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        (self.0)(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Sink {
+    /// Collects the output in memory, to be read with
+    /// [`Gs::output`](crate::Gs::output).
+    pub fn buffer() -> Sink {
+        Sink(Kind::Buffer(vec![]))
+    }
+
+    /// Like [`Sink::buffer`], but keeps only the first `max` bytes. The
+    /// program carries on printing; see [`Sink::truncated`].
+    pub fn bounded(max: usize) -> Sink {
+        Sink(Kind::Bounded {
+            buf: vec![],
+            max,
+            truncated: false,
+        })
+    }
+
+    /// Writes the output through to `w` as it is printed.
+    pub fn writer(w: impl Write + 'static) -> Sink {
+        Sink(Kind::Writer(Box::new(w)))
+    }
+
+    /// Writes the output to the process's standard output, buffered until
+    /// [`Gs::flush`](crate::Gs::flush) or the buffer fills.
+    pub fn stdout() -> Sink {
+        Sink::writer(io::BufWriter::new(io::stdout()))
+    }
+
+    /// Calls `f` with each piece of output as it is printed.
+    pub fn callback(f: impl FnMut(&[u8]) + 'static) -> Sink {
+        Sink::writer(Callback(f))
+    }
+
+    /// The output collected so far; empty for sinks that write it elsewhere.
+    pub fn bytes(&self) -> &[u8] {
+        match &self.0 {
+            Kind::Buffer(buf) | Kind::Bounded { buf, .. } => buf,
+            Kind::Writer(_) => &[],
+        }
+    }
+
+    /// Removes and returns the output collected so far.
+    pub fn take(&mut self) -> Vec<u8> {
+        match &mut self.0 {
+            Kind::Buffer(buf) | Kind::Bounded { buf, .. } => std::mem::take(buf),
+            Kind::Writer(_) => vec![],
+        }
+    }
+
+    /// Whether a bounded sink had to drop output.
+    pub fn truncated(&self) -> bool {
+        matches!(
+            self.0,
+            Kind::Bounded {
+                truncated: true,
+                ..
+            }
+        )
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.0 {
+            Kind::Buffer(buf) => buf.extend_from_slice(bytes),
+            Kind::Bounded {
+                buf,
+                max,
+                truncated,
+            } => {
+                let room = max.saturating_sub(buf.len());
+                if bytes.len() > room {
+                    *truncated = true;
+                }
+                buf.extend_from_slice(&bytes[..bytes.len().min(room)]);
+            }
+            Kind::Writer(w) => w.write_all(bytes)?,
+        }
+        Ok(())
+    }
+
+    /// Forgets collected output past the first `len` bytes.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        match &mut self.0 {
+            Kind::Buffer(buf) => buf.truncate(len),
+            Kind::Bounded {
+                buf,
+                max,
+                truncated,
+            } => {
+                buf.truncate(len);
+                *truncated &= len >= *max;
+            }
+            Kind::Writer(_) => {}
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Kind::Writer(w) => w.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for Sink {
+    fn default() -> Sink {
+        Sink::buffer()
+    }
+}
@@ -0,0 +1,273 @@
+use crate::error::{ErrorKind, GsError, Limit};
+use crate::gs::{Config, Gs};
+use crate::value::Gval;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A program to judge: what it is given, and what it should print.
+///
+/// A directory of cases holds a `NAME.gs` for each, with its input in
+/// `NAME.in`, which may be left out for no input, and the expected output
+/// in `NAME.out`. An optional `NAME.judge` says how to judge it, a setting
+/// per line:
+///
+/// ```text
+/// # lines may end in spaces
+/// compare = trailing-whitespace
+/// timeout-ms = 500
+/// ```
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub name: String,
+    pub program: Vec<u8>,
+    pub input: Vec<u8>,
+    /// `None` if there's no `NAME.out` yet.
+    pub expected: Option<Vec<u8>>,
+    /// Where the expected output is, or would be.
+    pub expected_path: PathBuf,
+    /// How to compare the output, if not the runner's default way.
+    pub compare: Option<Compare>,
+    /// How long the program may run, if not as long as the runner allows.
+    pub timeout: Option<Duration>,
+}
+
+/// How output is compared with the expected output. Judges differ.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compare {
+    /// Byte for byte.
+    #[default]
+    Exact,
+    /// Ignoring whitespace at the ends of lines, and blank lines at the end.
+    TrailingWhitespace,
+    /// Treating `\r\n` as `\n`.
+    Crlf,
+}
+
+impl Compare {
+    /// `output` with what this comparison ignores taken out.
+    pub fn normalize(self, output: &[u8]) -> Vec<u8> {
+        match self {
+            Compare::Exact => output.to_vec(),
+            Compare::TrailingWhitespace => {
+                let lines: Vec<&[u8]> = output
+                    .split(|&b| b == b'\n')
+                    .map(<[u8]>::trim_ascii_end)
+                    .collect();
+                lines.join(&b'\n').trim_ascii_end().to_vec()
+            }
+            Compare::Crlf => {
+                let mut normalized = Vec::with_capacity(output.len());
+                for (i, &b) in output.iter().enumerate() {
+                    if b != b'\r' || output.get(i + 1) != Some(&b'\n') {
+                        normalized.push(b);
+                    }
+                }
+                normalized
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Compare {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Compare, String> {
+        match s {
+            "exact" => Ok(Compare::Exact),
+            "trailing-whitespace" => Ok(Compare::TrailingWhitespace),
+            "crlf" => Ok(Compare::Crlf),
+            _ => Err(format!(
+                "unknown comparison `{}`; expected exact, trailing-whitespace or crlf",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Compare {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Compare::Exact => "exact",
+            Compare::TrailingWhitespace => "trailing-whitespace",
+            Compare::Crlf => "crlf",
+        })
+    }
+}
+
+/// What running a [`Case`] did.
+#[derive(Debug)]
+pub struct Outcome {
+    /// Everything printed, including the final stack.
+    pub output: Vec<u8>,
+    /// Why the program stopped early, if it did. The output up to there is
+    /// still judged, as anarchy golf judges what reached stdout.
+    pub error: Option<GsError>,
+    pub elapsed: Duration,
+}
+
+impl Case {
+    /// The cases in `dir`, by name.
+    pub fn load_dir(dir: &Path) -> io::Result<Vec<Case>> {
+        let mut cases = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "gs") {
+                continue;
+            }
+            let optional = |path: &Path| match std::fs::read(path) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            };
+            let expected_path = path.with_extension("out");
+            let judge = path.with_extension("judge");
+            let (compare, timeout) = match optional(&judge)? {
+                Some(bytes) => judging(&bytes).map_err(|message| {
+                    let message = format!("{}: {}", judge.display(), message);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })?,
+                None => (None, None),
+            };
+            cases.push(Case {
+                name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+                program: std::fs::read(&path)?,
+                input: optional(&path.with_extension("in"))?.unwrap_or_default(),
+                expected: optional(&expected_path)?,
+                expected_path,
+                compare,
+                timeout,
+            });
+        }
+        cases.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(cases)
+    }
+
+    /// Runs the program on the input with `config`, and collects what it
+    /// prints, the final stack included, as the binary does. The case's
+    /// timeout replaces the configured one.
+    pub fn run(&self, mut config: Config) -> Outcome {
+        if self.timeout.is_some() {
+            config.limits.time = self.timeout;
+        }
+        let start = Instant::now();
+        let mut gs = Gs::with_config(config);
+        gs.push(Gval::from(self.input.clone()));
+        let error = gs.run(&self.program).and_then(|_| gs.output_stack()).err();
+        Outcome {
+            output: gs.take_output(),
+            error,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// How `output` differs from the expected output, compared the case's
+    /// way or else `default`, or `None` if it doesn't.
+    pub fn mismatch(&self, output: &[u8], default: Compare) -> Option<String> {
+        let Some(expected) = &self.expected else {
+            return Some(format!(
+                "no expected output in {}",
+                self.expected_path.display()
+            ));
+        };
+        let compare = self.compare.unwrap_or(default);
+        let (expected, output) = (compare.normalize(expected), compare.normalize(output));
+        if expected == output {
+            return None;
+        }
+        let diff = diff(&expected, &output);
+        match compare {
+            Compare::Exact => Some(diff),
+            _ => Some(format!("{} (compared {})", diff, compare)),
+        }
+    }
+
+    /// Makes `output` the expected output, and writes it to
+    /// [`expected_path`](Case::expected_path).
+    pub fn bless(&mut self, output: Vec<u8>) -> io::Result<()> {
+        std::fs::write(&self.expected_path, &output)?;
+        self.expected = Some(output);
+        Ok(())
+    }
+}
+
+impl Outcome {
+    /// Whether the program ran out of time, which fails it however its
+    /// output compares.
+    pub fn timed_out(&self) -> bool {
+        matches!(
+            self.error,
+            Some(GsError {
+                kind: ErrorKind::LimitExceeded {
+                    limit: Limit::Time,
+                    ..
+                },
+                ..
+            })
+        )
+    }
+}
+
+/// Reads the settings in a `NAME.judge`.
+fn judging(bytes: &[u8]) -> Result<(Option<Compare>, Option<Duration>), String> {
+    let (mut compare, mut timeout) = (None, None);
+    for line in String::from_utf8_lossy(bytes).lines() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("expected `key = value`, found `{}`", line));
+        };
+        match (key.trim(), value.trim()) {
+            ("compare", value) => compare = Some(value.parse()?),
+            ("timeout-ms", value) => {
+                let ms = value
+                    .parse()
+                    .map_err(|_| format!("`{}` isn't a number of milliseconds", value))?;
+                timeout = Some(Duration::from_millis(ms));
+            }
+            (key, _) => return Err(format!("unknown setting `{}`", key)),
+        }
+    }
+    Ok((compare, timeout))
+}
+
+/// Describes where `actual` first differs from `expected`, byte for byte,
+/// with the bytes around it escaped.
+fn diff(expected: &[u8], actual: &[u8]) -> String {
+    let at = expected
+        .iter()
+        .zip(actual)
+        .position(|(a, b)| a != b)
+        .unwrap_or(expected.len().min(actual.len()));
+    let line = 1 + expected[..at].iter().filter(|&&b| b == b'\n').count();
+    let col = 1 + at
+        - expected[..at]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |n| n + 1);
+    let around = |bytes: &[u8]| {
+        let start = at.saturating_sub(20);
+        let end = (at + 20).min(bytes.len());
+        let text = bytes.get(start..end).unwrap_or_default().escape_ascii();
+        let ellipsis = |more| if more { "..." } else { "" };
+        format!(
+            "{}\"{}\"{}",
+            ellipsis(start > 0),
+            text,
+            ellipsis(end < bytes.len())
+        )
+    };
+    format!(
+        "first difference at byte {} (line {}, column {}); expected {} bytes, got {}\n  expected: {}\n  actual:   {}",
+        at,
+        line,
+        col,
+        expected.len(),
+        actual.len(),
+        around(expected),
+        around(actual)
+    )
+}
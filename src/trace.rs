@@ -0,0 +1,96 @@
+use crate::gs::{Gs, Hook, Location};
+use crate::util::json_string;
+use crate::value::Gval;
+use std::io::Write;
+
+/// A [`Hook`] that writes a line for every token run: how deep it is, the
+/// token and where it is, and the stack after it.
+///
+/// Whitespace tokens are left out. Write errors are ignored, so a trace
+/// never stops the program it traces.
+pub struct Tracer<W: Write> {
+    pub out: W,
+    /// Cut values to this many bytes of their `p` form.
+    pub width: Option<usize>,
+    /// Write JSON lines instead of indented text.
+    pub json: bool,
+    /// The source the tokens' spans refer to, to show strings and blocks as
+    /// written.
+    pub source: Option<Vec<u8>>,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Plain text, with values in full.
+    pub fn new(out: W) -> Tracer<W> {
+        Tracer {
+            out,
+            width: None,
+            json: false,
+            source: None,
+        }
+    }
+
+    fn token(&self, token: &Location) -> Vec<u8> {
+        if let Some(name) = token.assigns {
+            return [b":", name].concat();
+        }
+        let written = token
+            .span
+            .and_then(|s| self.source.as_ref()?.get(s.start..s.end));
+        written.unwrap_or(token.lexeme).to_vec()
+    }
+
+    fn value(&self, val: &Gval) -> Vec<u8> {
+        let mut bytes = val.clone().inspect();
+        if let Some(width) = self.width {
+            if bytes.len() > width {
+                bytes.truncate(width);
+                bytes.extend_from_slice(b"...");
+            }
+        }
+        bytes
+    }
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn after(&mut self, gs: &Gs, token: &Location) {
+        if token.lexeme.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+        let stack: Vec<Vec<u8>> = gs.stack().iter().map(|v| self.value(v)).collect();
+        let text = self.token(token);
+        let line = if self.json {
+            let stack: Vec<String> = stack.iter().map(|v| json_string(v)).collect();
+            let span = token.span.map_or("null".to_owned(), |s| {
+                format!(
+                    "{{\"start\":{},\"end\":{},\"line\":{},\"col\":{}}}",
+                    s.start, s.end, s.line, s.col
+                )
+            });
+            let block = token
+                .block
+                .map_or("null".to_owned(), |s| format!("[{},{}]", s.start, s.end));
+            format!(
+                "{{\"depth\":{},\"token\":{},\"span\":{},\"block\":{},\"stack\":[{}]}}",
+                token.depth,
+                json_string(&text),
+                span,
+                block,
+                stack.join(",")
+            )
+        } else {
+            let at = token
+                .span
+                .map_or(String::new(), |s| format!(" @{}:{}", s.line, s.col));
+            let stack: Vec<_> = stack.iter().map(|v| String::from_utf8_lossy(v)).collect();
+            format!(
+                "{}{}{} -> [{}]",
+                "  ".repeat(token.depth),
+                String::from_utf8_lossy(&text),
+                at,
+                stack.join(" ")
+            )
+        };
+        let _ = writeln!(self.out, "{}", line);
+    }
+}
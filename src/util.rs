@@ -1,47 +1,33 @@
+use crate::int::Int;
 use core::cmp::Ordering;
 use core::hash::Hash;
-use num::BigInt;
-use num::Integer;
-use num::One;
-use num::Signed;
 use num::ToPrimitive;
-use num::Zero;
 use std::collections::HashSet;
 
-pub fn to_byte(n: BigInt) -> u8 {
+pub fn to_byte(n: Int) -> u8 {
     n.mod_floor(&256.into()).to_u8().unwrap()
 }
 
-pub fn repeat<T: Clone>(a: Vec<T>, mut n: BigInt) -> Vec<T> {
-    let mut v = vec![];
-    while n.is_positive() {
-        v.extend(a.clone());
-        n -= 1;
-    }
-    v
-}
-
-pub fn chunk<'a, T: Clone>(a: &'a mut Vec<T>, n: BigInt) -> Vec<&'a [T]> {
-    if a.len() == 0 {
-        return vec![];
-    }
-    if n.is_zero() {
-        panic!("chunk division by 0");
-    }
+/// Splits `a` into runs of `n`, from the end if `n` is negative. `n` must
+/// not be zero.
+pub fn chunk<T: Clone>(a: &[T], n: Int) -> Vec<Vec<T>> {
+    let size = n.abs().to_usize().unwrap_or(usize::MAX);
     if n.is_negative() {
-        a.reverse();
+        let reversed: Vec<T> = a.iter().rev().cloned().collect();
+        reversed.chunks(size).map(|c| c.to_vec()).collect()
+    } else {
+        a.chunks(size).map(|c| c.to_vec()).collect()
     }
-    a.chunks(n.abs().to_usize().unwrap()).collect()
 }
 
-pub fn split<T: Clone + Eq>(a: Vec<T>, sep: Vec<T>, clean: bool) -> Vec<Vec<T>> {
+pub fn split<T: Clone + Eq>(a: &[T], sep: &[T], clean: bool) -> Vec<Vec<T>> {
     let mut r: Vec<Vec<T>> = vec![];
     let mut i: Vec<T> = vec![];
     let mut j: usize = 0;
 
     while j < a.len() {
         if j + sep.len() <= a.len() && a[j..j + sep.len()].iter().eq(sep.iter()) {
-            if !clean || i.len() > 0 {
+            if !clean || !i.is_empty() {
                 r.push(i);
             }
             i = vec![];
@@ -51,76 +37,72 @@ pub fn split<T: Clone + Eq>(a: Vec<T>, sep: Vec<T>, clean: bool) -> Vec<Vec<T>>
             j += 1;
         }
     }
-    if !clean || i.len() > 0 {
+    if !clean || !i.is_empty() {
         r.push(i);
     }
     r
 }
 
-pub fn every_nth<T>(a: Vec<T>, n: BigInt) -> Vec<T> {
-    let m = n.abs().to_usize().unwrap();
+/// Every `n`th element, from the end if `n` is negative. `n` must not be
+/// zero.
+pub fn every_nth<T: Clone>(a: &[T], n: Int) -> Vec<T> {
+    let m = n.abs().to_usize().unwrap_or(usize::MAX);
     if n.is_negative() {
-        a.into_iter().rev().step_by(m).collect()
+        a.iter().rev().step_by(m).cloned().collect()
     } else {
-        a.into_iter().step_by(m).collect()
+        a.iter().step_by(m).cloned().collect()
     }
 }
 
-pub fn set_subtract<T: Eq>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
-    a.into_iter().filter(|x| !b.contains(&x)).collect()
+pub fn set_subtract<T: Clone + Eq>(a: &[T], b: &[T]) -> Vec<T> {
+    a.iter().filter(|x| !b.contains(x)).cloned().collect()
 }
 
-pub fn set_or<T: Clone + Eq + Hash>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
+pub fn set_or<T: Clone + Eq + Hash>(a: &[T], b: &[T]) -> Vec<T> {
     let mut seen: HashSet<T> = HashSet::new();
     let mut result: Vec<T> = vec![];
-    for v in a.into_iter().chain(b.into_iter()) {
+    for v in a.iter().chain(b) {
         if seen.insert(v.clone()) {
-            result.push(v)
+            result.push(v.clone())
         }
     }
     result
 }
 
-pub fn set_and<T: Clone + Eq + Hash>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
-    let mut in_a: HashSet<T> = HashSet::new();
+pub fn set_and<T: Clone + Eq + Hash>(a: &[T], b: &[T]) -> Vec<T> {
+    let mut in_a: HashSet<&T> = HashSet::new();
     let mut result: Vec<T> = vec![];
     for v in a {
         in_a.insert(v);
     }
-    let mut seen: HashSet<T> = HashSet::new();
+    let mut seen: HashSet<&T> = HashSet::new();
     for v in b {
-        if in_a.contains(&v) && seen.insert(v.clone()) {
-            result.push(v)
+        if in_a.contains(v) && seen.insert(v) {
+            result.push(v.clone())
         }
     }
     result
 }
 
-pub fn set_xor<T: Clone + Eq + Hash>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
-    let mut in_a: HashSet<T> = HashSet::new();
-    let mut in_b: HashSet<T> = HashSet::new();
-    let mut seen: HashSet<T> = HashSet::new();
+pub fn set_xor<T: Clone + Eq + Hash>(a: &[T], b: &[T]) -> Vec<T> {
+    let in_a: HashSet<&T> = a.iter().collect();
+    let in_b: HashSet<&T> = b.iter().collect();
+    let mut seen: HashSet<&T> = HashSet::new();
     let mut result: Vec<T> = vec![];
-    for v in &a {
-        in_a.insert(v.clone());
-    }
-    for v in &b {
-        in_b.insert(v.clone());
-    }
-    for v in a.into_iter().chain(b.into_iter()) {
-        if !seen.contains(&v) && (in_a.contains(&v) ^ in_b.contains(&v)) {
-            seen.insert(v.clone());
-            result.push(v)
+    for v in a.iter().chain(b) {
+        if !seen.contains(v) && (in_a.contains(v) ^ in_b.contains(v)) {
+            seen.insert(v);
+            result.push(v.clone())
         }
     }
     result
 }
 
-pub fn index<T>(a: &Vec<T>, i: BigInt) -> Option<&T> {
-    let l: BigInt = a.len().into();
+pub fn index<T>(a: &[T], i: Int) -> Option<&T> {
+    let l: Int = a.len().into();
     if i >= l {
         None
-    } else if i >= BigInt::zero() && i < l {
+    } else if i >= Int::zero() && i < l {
         Some(&a[i.to_usize().unwrap()])
     } else if i >= -l.clone() {
         Some(&a[(i + l).to_usize().unwrap()])
@@ -129,26 +111,26 @@ pub fn index<T>(a: &Vec<T>, i: BigInt) -> Option<&T> {
     }
 }
 
-pub fn slice<T: Clone>(o: Ordering, a: Vec<T>, i: BigInt) -> Vec<T> {
+pub fn slice<T: Clone>(o: Ordering, a: &[T], i: Int) -> Vec<T> {
     let l = a.len();
-    let lb: BigInt = a.len().into();
+    let lb: Int = a.len().into();
     let ix = if i >= lb {
         l
-    } else if i >= BigInt::zero() {
+    } else if i >= Int::zero() {
         i.to_usize().unwrap()
     } else if i >= -lb.clone() {
-        (i + l).to_usize().unwrap()
+        (i + lb).to_usize().unwrap()
     } else {
         0
     };
     match o {
         Ordering::Less => a[0..ix].to_vec(),
         Ordering::Greater => a[ix..].to_vec(),
-        _ => panic!(),
+        Ordering::Equal => unreachable!(),
     }
 }
 
-pub fn string_index(haystack: &[u8], needle: &[u8]) -> BigInt {
+pub fn string_index(haystack: &[u8], needle: &[u8]) -> Int {
     let hl = haystack.len();
     let nl = needle.len();
     if nl <= hl {
@@ -158,5 +140,23 @@ pub fn string_index(haystack: &[u8], needle: &[u8]) -> BigInt {
             }
         }
     }
-    return -BigInt::one();
+    (-1).into()
+}
+
+/// `bytes` as a JSON string literal, decoded as UTF-8 lossily.
+pub fn json_string(bytes: &[u8]) -> String {
+    let mut json = String::from("\"");
+    for c in String::from_utf8_lossy(bytes).chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
 }
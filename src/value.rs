@@ -1,15 +1,122 @@
+use crate::code::{compile, Code, CompileError, Symbols};
 use crate::coerce::flatten_append;
 use crate::coerce::{coerce, Coerced};
+use crate::error::GsError;
+use crate::int::Int;
 use num::BigInt;
-use num::One;
-use num::Zero;
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
+/// A GolfScript value.
+///
+/// Arrays, strings and blocks are reference-counted, so cloning a value is
+/// cheap; the contents are copied only when a shared value is changed.
+///
+/// Values order first by type (integers, then arrays, strings and blocks) and
+/// then by contents, which is the order `$` sorts mixed arrays in.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Gval {
-    Int(BigInt),
-    Arr(Vec<Gval>),
-    Str(Vec<u8>),
-    Blk(Vec<u8>),
+    /// An arbitrary-precision integer.
+    Int(Int),
+    /// An array of values.
+    Arr(Rc<Vec<Gval>>),
+    /// A byte string; GolfScript strings are not required to be UTF-8.
+    Str(Rc<Vec<u8>>),
+    /// A block of code.
+    Blk(Block),
+}
+
+/// The code of a block value: its source between the braces, plus the
+/// compiled form, built the first time the block runs and shared by clones.
+///
+/// Blocks compare, hash and print by source alone.
+#[derive(Clone)]
+pub struct Block(Rc<BlockData>);
+
+#[derive(Clone)]
+struct BlockData {
+    src: Vec<u8>,
+    code: OnceCell<Rc<Code>>,
+}
+
+impl Block {
+    pub fn new(src: Vec<u8>) -> Block {
+        Block(Rc::new(BlockData {
+            src,
+            code: OnceCell::new(),
+        }))
+    }
+
+    pub(crate) fn compiled(src: Vec<u8>, code: Code) -> Block {
+        Block(Rc::new(BlockData {
+            src,
+            code: OnceCell::from(Rc::new(code)),
+        }))
+    }
+
+    /// The source between the braces.
+    pub fn source(&self) -> &[u8] {
+        &self.0.src
+    }
+
+    /// The source between the braces, copied only if the block is shared.
+    pub fn into_source(self) -> Vec<u8> {
+        Rc::unwrap_or_clone(self.0).src
+    }
+
+    /// The compiled block, with ids from `symbols`.
+    pub(crate) fn code(&self, symbols: &mut Symbols) -> Result<Rc<Code>, CompileError> {
+        match self.0.code.get() {
+            Some(code) if code.table == symbols.table() => Ok(code.clone()),
+            // compiled by another interpreter; its ids mean nothing here
+            Some(_) => Ok(Rc::new(compile(&self.0.src, None, symbols)?)),
+            None => {
+                let code = Rc::new(compile(&self.0.src, None, symbols)?);
+                Ok(self.0.code.get_or_init(|| code).clone())
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Block {
+    fn from(src: Vec<u8>) -> Block {
+        Block::new(src)
+    }
+}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{}}}", String::from_utf8_lossy(self.source()))
+    }
+}
+
+impl PartialEq for Block {
+    fn eq(&self, other: &Block) -> bool {
+        self.source() == other.source()
+    }
+}
+
+impl Eq for Block {}
+
+impl PartialOrd for Block {
+    fn partial_cmp(&self, other: &Block) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Block {
+    fn cmp(&self, other: &Block) -> Ordering {
+        self.source().cmp(other.source())
+    }
+}
+
+impl Hash for Block {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source().hash(state)
+    }
 }
 
 impl From<u8> for Gval {
@@ -23,16 +130,72 @@ impl From<usize> for Gval {
         Gval::Int(byte.into())
     }
 }
+
+impl From<i32> for Gval {
+    fn from(n: i32) -> Self {
+        Gval::Int(n.into())
+    }
+}
+
+impl From<i64> for Gval {
+    fn from(n: i64) -> Self {
+        Gval::Int(n.into())
+    }
+}
+
+impl From<BigInt> for Gval {
+    fn from(n: BigInt) -> Self {
+        Gval::Int(n.into())
+    }
+}
+
+impl From<Int> for Gval {
+    fn from(n: Int) -> Self {
+        Gval::Int(n)
+    }
+}
+
+impl From<&str> for Gval {
+    fn from(s: &str) -> Self {
+        Gval::Str(Rc::new(s.as_bytes().to_vec()))
+    }
+}
+
+impl From<Vec<u8>> for Gval {
+    fn from(bs: Vec<u8>) -> Self {
+        Gval::Str(Rc::new(bs))
+    }
+}
+
+impl From<Vec<Gval>> for Gval {
+    fn from(vs: Vec<Gval>) -> Self {
+        Gval::Arr(Rc::new(vs))
+    }
+}
+
 impl Gval {
+    /// `1` for true and `0` for false.
     pub fn bool(value: bool) -> Self {
-        Gval::Int(if value { BigInt::one() } else { BigInt::zero() })
+        Gval::Int(i64::from(value).into())
+    }
+
+    /// `"int"`, `"array"`, `"string"` or `"block"`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Gval::Int(_) => "int",
+            Gval::Arr(_) => "array",
+            Gval::Str(_) => "string",
+            Gval::Blk(_) => "block",
+        }
     }
 
+    /// Whether GolfScript treats the value as false: `0`, `[]`, `""` or `{}`.
     pub fn falsey(&self) -> bool {
         match self {
-            Gval::Int(a) => *a == BigInt::zero(),
-            Gval::Arr(vs) => vs.len() == 0,
-            Gval::Str(bs) | Gval::Blk(bs) => bs.len() == 0,
+            Gval::Int(a) => a.is_zero(),
+            Gval::Arr(vs) => vs.is_empty(),
+            Gval::Str(bs) => bs.is_empty(),
+            Gval::Blk(b) => b.source().is_empty(),
         }
     }
 
@@ -40,32 +203,36 @@ impl Gval {
         !self.falsey()
     }
 
+    /// The bytes `print` writes for this value.
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_gs(self) -> Vec<u8> {
         match self {
-            Gval::Int(a) => a.to_str_radix(10).into_bytes(),
+            Gval::Int(a) => a.to_string().into_bytes(),
             Gval::Arr(vs) => {
                 let mut bytes: Vec<u8> = vec![];
-                for v in vs {
+                for v in Rc::unwrap_or_clone(vs) {
                     bytes.extend(v.to_gs());
                 }
                 bytes
             }
-            Gval::Str(bs) => bs,
-            Gval::Blk(bs) => {
-                let mut bytes: Vec<u8> = vec!['{' as u8];
-                bytes.extend(bs);
-                bytes.push('}' as u8);
+            Gval::Str(bs) => Rc::unwrap_or_clone(bs),
+            Gval::Blk(b) => {
+                let mut bytes: Vec<u8> = vec![b'{'];
+                bytes.extend(b.source());
+                bytes.push(b'}');
                 bytes
             }
         }
     }
 
+    /// The bytes `p` and `` ` `` produce: GolfScript source that evaluates
+    /// back to this value.
     pub fn inspect(self) -> Vec<u8> {
         match self {
             Gval::Arr(vs) => {
                 let mut bytes: Vec<u8> = vec![b'['];
                 let mut s = false;
-                for v in vs {
+                for v in Rc::unwrap_or_clone(vs) {
                     if s {
                         bytes.push(b' ');
                     }
@@ -77,7 +244,7 @@ impl Gval {
             }
             Gval::Str(bs) => {
                 let mut bytes: Vec<u8> = vec![b'"'];
-                for b in bs {
+                for &b in bs.iter() {
                     if b == b'\'' {
                         bytes.push(b)
                     } else {
@@ -91,73 +258,88 @@ impl Gval {
         }
     }
 
-    pub fn plus(self, other: Gval) -> Gval {
+    /// Roughly how many bytes the value occupies. Shared payloads are counted
+    /// once per reference.
+    pub(crate) fn heap_size(&self) -> usize {
+        match self {
+            Gval::Arr(vs) => vs.iter().map(Gval::heap_size).sum::<usize>() + size_of::<Gval>(),
+            _ => self.shallow_size(),
+        }
+    }
+
+    /// [`Gval::heap_size`] without looking inside the items of an array.
+    pub(crate) fn shallow_size(&self) -> usize {
+        size_of::<Gval>()
+            + match self {
+                Gval::Int(n) if n.bits() > 63 => n.bits() as usize / 8,
+                Gval::Int(_) => 0,
+                Gval::Arr(vs) => vs.len() * size_of::<Gval>(),
+                Gval::Str(bs) => bs.len(),
+                Gval::Blk(b) => b.source().len(),
+            }
+    }
+
+    pub(crate) fn plus(self, other: Gval) -> Gval {
         match coerce(self, other) {
             Coerced::Ints(x, y) => Gval::Int(x + y),
             Coerced::Arrs(mut x, y) => {
-                x.extend(y);
+                Rc::make_mut(&mut x).extend(y.iter().cloned());
                 Gval::Arr(x)
             }
             Coerced::Strs(mut x, y) => {
-                x.extend(y);
+                Rc::make_mut(&mut x).extend_from_slice(&y);
                 Gval::Str(x)
             }
             Coerced::Blks(x, y) => {
-                let mut joined = x.clone();
+                let mut joined = x;
                 joined.push(b' ');
                 joined.extend(y);
-                Gval::Blk(joined)
+                Gval::Blk(joined.into())
             }
         }
     }
 
-    pub fn factory(&self) -> Gval {
+    pub(crate) fn factory(&self) -> Gval {
         match self {
-            Gval::Int(_) => Gval::Int(BigInt::zero()),
-            Gval::Arr(_) => Gval::Arr(vec![]),
-            Gval::Str(_) => Gval::Str(vec![]),
-            Gval::Blk(_) => Gval::Blk(vec![]),
+            Gval::Int(_) => Gval::Int(Int::zero()),
+            Gval::Arr(_) => Gval::Arr(Rc::default()),
+            Gval::Str(_) => Gval::Str(Rc::default()),
+            Gval::Blk(_) => Gval::Blk(Block::new(vec![])),
         }
     }
 
-    pub fn push(&mut self, other: Gval) {
+    pub(crate) fn push(&mut self, other: Gval) -> Result<(), GsError> {
         match self {
-            Gval::Int(_) => panic!("push"),
-            Gval::Arr(vs) => vs.push(other),
-            Gval::Str(vs) => flatten_append(vs, other),
-            Gval::Blk(vs) => flatten_append(vs, other),
-        }
-    }
-
-    pub fn unwrap_int(self) -> BigInt {
-        match self {
-            Gval::Int(n) => n,
-            _ => panic!("expected int"),
-        }
-    }
-
-    pub fn unwrap_arr(self) -> Vec<Gval> {
-        match self {
-            Gval::Arr(a) => a,
-            _ => panic!("expected array"),
+            Gval::Int(_) => return Err(GsError::type_mismatch("", &["int", other.type_name()])),
+            Gval::Arr(vs) => Rc::make_mut(vs).push(other),
+            Gval::Str(vs) => flatten_append(Rc::make_mut(vs), other),
+            Gval::Blk(b) => {
+                let data = Rc::make_mut(&mut b.0);
+                flatten_append(&mut data.src, other);
+                data.code = OnceCell::new();
+            }
         }
+        Ok(())
     }
 
-    pub fn as_arr(self) -> Vec<Gval> {
+    /// The elements of an array, or the bytes of a string or block; `None`
+    /// for an int.
+    pub(crate) fn elements(&self) -> Option<Vec<Gval>> {
         match self {
-            Gval::Int(_) => panic!("as_arr"),
-            Gval::Arr(a) => a,
-            Gval::Str(a) | Gval::Blk(a) => a.into_iter().map(|b| b.into()).collect(),
+            Gval::Int(_) => None,
+            Gval::Arr(a) => Some(a.to_vec()),
+            Gval::Str(a) => Some(a.iter().map(|&b| b.into()).collect()),
+            Gval::Blk(b) => Some(b.source().iter().map(|&b| b.into()).collect()),
         }
     }
 }
 
-pub fn join(a: Vec<Gval>, sep: Gval) -> Gval {
-    let mut a = a.into_iter();
+pub fn join(a: &[Gval], sep: Gval) -> Gval {
+    let mut a = a.iter().cloned();
     match a.next() {
         None => match sep {
-            Gval::Arr(_) => Gval::Arr(vec![]),
-            _ => Gval::Str(vec![]),
+            Gval::Arr(_) => Gval::Arr(Rc::default()),
+            _ => Gval::Str(Rc::default()),
         },
         Some(mut r) => {
             r = coerce(r, sep.clone()).left();
@@ -0,0 +1 @@
+255 16base
\ No newline at end of file
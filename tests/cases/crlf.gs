@@ -0,0 +1 @@
+[1 2]n*
\ No newline at end of file
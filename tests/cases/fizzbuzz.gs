@@ -0,0 +1 @@
+15,{)..3%!'Fizz'*\5%!'Buzz'*+\or}%n*
\ No newline at end of file
@@ -0,0 +1 @@
+~{.@\%.}do;
\ No newline at end of file
@@ -0,0 +1 @@
+[1 "a" {x}]p
\ No newline at end of file
@@ -0,0 +1 @@
+10000,,' '0{1+.5000<}do' '0 3000{1+}*
\ No newline at end of file
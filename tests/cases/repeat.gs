@@ -0,0 +1 @@
+"ab"3*
\ No newline at end of file
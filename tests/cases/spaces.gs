@@ -0,0 +1 @@
+"a  \nb\t"
\ No newline at end of file
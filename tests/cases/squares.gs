@@ -0,0 +1 @@
+10,{.*}%
\ No newline at end of file
@@ -0,0 +1 @@
+[[1 2][3 4]]zip p
\ No newline at end of file
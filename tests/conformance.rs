@@ -0,0 +1,74 @@
+use golfscript_rs::{Case, Compare, Config, Limits};
+use std::time::Duration;
+
+const CASES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cases");
+
+/// The configuration the `test` subcommand judges with by default.
+fn config() -> Config {
+    Config {
+        limits: Limits {
+            time: Some(Duration::from_secs(10)),
+            output: Some(1 << 20),
+            ..Limits::none()
+        },
+        ..Config::strict()
+    }
+}
+
+#[test]
+fn cases() {
+    let cases = Case::load_dir(CASES.as_ref()).unwrap();
+    assert!(!cases.is_empty());
+    let mut failures = vec![];
+    for case in &cases {
+        let outcome = case.run(config());
+        assert!(!outcome.timed_out(), "{} timed out", case.name);
+        if let Some(diff) = case.mismatch(&outcome.output, Compare::Exact) {
+            failures.push(format!("{}: {}", case.name, diff));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn blesses_what_the_program_prints() {
+    let dir = std::env::temp_dir().join(format!("golfscript-bless-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = std::path::Path::new(CASES).join("loops.gs");
+    std::fs::copy(program, dir.join("loops.gs")).unwrap();
+    let mut cases = Case::load_dir(&dir).unwrap();
+    assert_eq!(cases[0].expected, None);
+    let outcome = cases[0].run(config());
+    cases[0].bless(outcome.output).unwrap();
+    let cases = Case::load_dir(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    // not what a lenient run, which stops each loop at 2000, would print
+    assert_eq!(
+        cases[0].expected.as_deref(),
+        Some(&b"10000 5000 3000\n"[..])
+    );
+}
+
+#[test]
+fn doesnt_bless_cases_that_stop() {
+    let dir = std::env::temp_dir().join(format!("golfscript-stops-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("ok.gs"), "1 2+").unwrap();
+    std::fs::write(dir.join("stops.gs"), "'x'p 1 0/").unwrap();
+    std::fs::write(dir.join("stops.out"), "y\n").unwrap();
+    let run = std::process::Command::new(env!("CARGO_BIN_EXE_golfscript-rs"))
+        .arg("test")
+        .arg(&dir)
+        .arg("--bless")
+        .output()
+        .unwrap();
+    let cases = Case::load_dir(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(!run.status.success(), "{}", stdout);
+    assert!(stdout.contains("BLESS ok\n"), "{}", stdout);
+    assert!(stdout.contains("FAIL stops"), "{}", stdout);
+    assert!(stdout.contains("passed 1/2"), "{}", stdout);
+    assert_eq!(cases[0].expected.as_deref(), Some(&b"3\n"[..]));
+    assert_eq!(cases[1].expected.as_deref(), Some(&b"y\n"[..]));
+}
@@ -0,0 +1,26 @@
+use golfscript_rs::{golfscript_bytes, ErrorKind, Gs};
+
+fn mismatch(code: &[u8]) -> (String, Vec<&'static str>) {
+    let e = Gs::new().run(code).unwrap_err();
+    match e.kind {
+        ErrorKind::TypeMismatch { op, types } => (op, types),
+        kind => panic!("{:?}", kind),
+    }
+}
+
+#[test]
+fn base_of_non_digits() {
+    assert_eq!(
+        mismatch(b"[\"a\"]2base"),
+        ("base".to_owned(), vec!["string"])
+    );
+    assert_eq!(mismatch(b"[[1]]2base"), ("base".to_owned(), vec!["array"]));
+    // a lenient run carries on, leaving the operands
+    assert_eq!(golfscript_bytes(b"", b"[\"a\"]2base;p"), b"[\"a\"]\n\n");
+}
+
+#[test]
+fn zip_of_ints() {
+    assert_eq!(mismatch(b"[1 2]zip"), ("zip".to_owned(), vec!["int"]));
+    assert_eq!(golfscript_bytes(b"", b"[1 2]zip p"), b"[1 2]\n\n");
+}
@@ -0,0 +1,117 @@
+use golfscript_rs::{Config, ErrorKind, Gs, Gval, Limit, Limits};
+use std::time::{Duration, Instant};
+
+fn timed(ms: u64) -> Gs {
+    Gs::with_config(Config {
+        limits: Limits {
+            time: Some(Duration::from_millis(ms)),
+            ..Limits::none()
+        },
+        ..Config::strict()
+    })
+}
+
+#[test]
+fn repeats_nothing_quickly() {
+    let mut gs = timed(100);
+    let start = Instant::now();
+    gs.run(b"[]10000000000*,\"\"10000000000*,").unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(gs.stack(), [Gval::from(0), Gval::from(0)]);
+}
+
+#[test]
+fn time_limit_stops_repeats() {
+    let mut gs = timed(100);
+    let start = Instant::now();
+    let e = gs.run(b"{[]10000000000*;\"x\"100000*;1}do").unwrap_err();
+    assert!(matches!(
+        e.kind,
+        ErrorKind::LimitExceeded {
+            limit: Limit::Time,
+            ..
+        }
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn cancel_stops_repeats() {
+    let mut gs = Gs::new();
+    let token = gs.cancel_token();
+    let watchdog = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        token.cancel();
+    });
+    let e = gs.run(b"{[]10000000000*;\"x\"100000*;1}do").unwrap_err();
+    watchdog.join().unwrap();
+    assert!(matches!(e.kind, ErrorKind::Cancelled { .. }));
+}
+
+fn configs(ms: Option<u64>) -> [Gs; 2] {
+    [Config::strict(), Config::lenient()].map(|config| {
+        Gs::with_config(Config {
+            limits: Limits {
+                time: ms.map(Duration::from_millis),
+                ..Limits::none()
+            },
+            ..config
+        })
+    })
+}
+
+fn limit(e: &golfscript_rs::GsError) -> Option<Limit> {
+    match e.kind {
+        ErrorKind::LimitExceeded { limit, .. } => Some(limit),
+        _ => None,
+    }
+}
+
+#[test]
+fn repeats() {
+    assert_eq!(
+        golfscript_rs::golfscript_bytes(b"", b"\"ab\"3*"),
+        b"ababab\n"
+    );
+    assert_eq!(
+        golfscript_rs::golfscript_bytes(b"", b"[1]3*p;"),
+        b"[1 1 1]\n\n"
+    );
+    assert_eq!(golfscript_rs::golfscript_bytes(b"", b"\"ab\"-1*"), b"\n");
+}
+
+#[test]
+fn repeat_too_large_to_hold_is_refused() {
+    for mut gs in configs(None) {
+        let e = gs.run(b"\"ab\" 9223372036854775807*").unwrap_err();
+        assert_eq!(limit(&e), Some(Limit::Heap));
+        let e = gs.run(b"[1] 99999999999999999999*").unwrap_err();
+        assert_eq!(limit(&e), Some(Limit::Heap));
+    }
+    // and doesn't take an embedding host down
+    golfscript_rs::golfscript_bytes(b"", b"\"ab\" 9223372036854775807*");
+}
+
+#[test]
+fn time_limit_stops_a_huge_repeat() {
+    for mut gs in configs(Some(50)) {
+        let start = Instant::now();
+        let e = gs.run(b"\"x\"2000000000*").unwrap_err();
+        assert_eq!(limit(&e), Some(Limit::Time));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
+
+#[test]
+fn cancel_stops_a_huge_repeat() {
+    for mut gs in configs(None) {
+        let token = gs.cancel_token();
+        let watchdog = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            token.cancel();
+        });
+        let e = gs.run(b"\"x\"2000000000*").unwrap_err();
+        watchdog.join().unwrap();
+        assert!(matches!(e.kind, ErrorKind::Cancelled { .. }));
+    }
+}